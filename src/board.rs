@@ -1,50 +1,224 @@
-use bevy::prelude::*;
+use crate::tetrominoes::TetrominoKind;
 
+/// Columns of a standard board.
 pub const BOARD_WIDTH: usize = 10;
-pub const BOARD_HEIGHT: usize = 20;
+/// Rows shown on screen on a standard board, counted from the bottom.
+pub const VISIBLE_HEIGHT: usize = 20;
+/// Hidden rows above the visible playfield that pieces spawn into.
+pub const BUFFER_HEIGHT: usize = 20;
+/// Narrowest board a horizontal I piece still fits across.
+pub const MIN_BOARD_WIDTH: usize = 4;
+/// Fewest visible rows a board can have.
+pub const MIN_VISIBLE_HEIGHT: usize = 1;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// What occupies one square of the board. Cells record what put them there rather than a
+/// colour, so rendering, skins and statistics all work from the same data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
 pub enum Cell {
+    #[default]
     Empty,
-    Filled(Color),
+    /// Part of a locked piece of this kind.
+    Filled(TetrominoKind),
+    /// Garbage rows sent in from outside the game (e.g. by an opponent).
+    Garbage,
 }
 
-impl Default for Cell {
-    fn default() -> Self {
-        Cell::Empty
+impl Cell {
+    pub fn is_filled(self) -> bool {
+        self != Cell::Empty
     }
 }
 
 impl Default for Board {
     fn default() -> Self {
-        Self {
-            cells: [[Cell::Empty; BOARD_WIDTH]; BOARD_HEIGHT],
-        }
+        Self::new(BOARD_WIDTH, VISIBLE_HEIGHT)
     }
 }
 
-#[derive(Resource)]
+/// The playfield: `width` columns by `height` rows, of which the bottom `visible_height` are
+/// shown. Rows count up from the bottom.
+#[derive(Clone, Debug)]
 pub struct Board {
-    pub cells: [[Cell; BOARD_WIDTH]; BOARD_HEIGHT],
+    width: usize,
+    height: usize,
+    visible_height: usize,
+    /// Row-major, bottom row first.
+    cells: Vec<Cell>,
+}
+
+/// Result of [`Board::clear_full_rows`]: which rows were removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineClear {
+    /// Indices of the removed rows (bottom to top), as they were before collapsing.
+    pub rows: Vec<usize>,
+}
+
+impl LineClear {
+    pub fn count(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
 }
 
 impl Board {
-    pub fn get(&self, x: i32, y: i32) -> Option<Cell> {
-        if x < 0 || y < 0 {
-            return None;
+    /// An empty board `width` columns wide showing `visible_height` rows, with
+    /// [`BUFFER_HEIGHT`] hidden rows on top. Sizes below [`MIN_BOARD_WIDTH`] and
+    /// [`MIN_VISIBLE_HEIGHT`] are raised to them.
+    pub fn new(width: usize, visible_height: usize) -> Self {
+        let width = width.max(MIN_BOARD_WIDTH);
+        let visible_height = visible_height.max(MIN_VISIBLE_HEIGHT);
+        let height = visible_height + BUFFER_HEIGHT;
+        Self {
+            width,
+            height,
+            visible_height,
+            cells: vec![Cell::Empty; width * height],
         }
-        let (x, y) = (x as usize, y as usize);
-        if x >= BOARD_WIDTH || y >= BOARD_HEIGHT {
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Total rows, the hidden buffer included.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn visible_height(&self) -> usize {
+        self.visible_height
+    }
+
+    /// The cell at `(x, y)`, or `None` outside the board.
+    pub fn get(&self, x: i32, y: i32) -> Option<Cell> {
+        let (x, y) = (usize::try_from(x).ok()?, usize::try_from(y).ok()?);
+        if x >= self.width || y >= self.height {
             return None;
         }
-        Some(self.cells[y][x])
+        Some(self.cells[y * self.width + x])
     }
 
     pub fn set(&mut self, x: i32, y: i32, cell: Cell) {
-        if let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) {
-            if x < BOARD_WIDTH && y < BOARD_HEIGHT {
-                self.cells[y][x] = cell;
+        if let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y))
+            && x < self.width
+            && y < self.height
+        {
+            self.cells[y * self.width + x] = cell;
+        }
+    }
+
+    pub fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn is_row_full(&self, y: usize) -> bool {
+        self.row(y).iter().all(|cell| cell.is_filled())
+    }
+
+    /// Remove every full row and shift the rows above it down, filling the top with empty rows.
+    pub fn clear_full_rows(&mut self) -> LineClear {
+        let mut cleared = LineClear::default();
+        let mut write = 0;
+        for read in 0..self.height {
+            if self.is_row_full(read) {
+                cleared.rows.push(read);
+                continue;
+            }
+            if write != read {
+                self.cells.copy_within(
+                    read * self.width..(read + 1) * self.width,
+                    write * self.width,
+                );
             }
+            write += 1;
+        }
+        self.cells[write * self.width..].fill(Cell::Empty);
+        cleared
+    }
+
+    /// Raise the stack by `rows` and fill the bottom with garbage rows, open at column `hole`.
+    /// Returns false if that pushed filled cells off the top of the board.
+    ///
+    /// # Panics
+    ///
+    /// If `hole` is not a column of the board, since garbage without a hole could never clear.
+    pub fn push_garbage(&mut self, rows: usize, hole: usize) -> bool {
+        assert!(
+            hole < self.width,
+            "garbage hole {hole} is outside a board {} wide",
+            self.width
+        );
+        let rows = rows.min(self.height);
+        let kept = self.height - rows;
+        let overflow = self.cells[kept * self.width..]
+            .iter()
+            .any(|cell| cell.is_filled());
+        self.cells.copy_within(0..kept * self.width, rows * self.width);
+        for row in self.cells[..rows * self.width].chunks_mut(self.width) {
+            row.fill(Cell::Garbage);
+            row[hole] = Cell::Empty;
+        }
+        !overflow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill_row(board: &mut Board, y: i32) {
+        for x in 0..board.width() as i32 {
+            board.set(x, y, Cell::Garbage);
+        }
+    }
+
+    #[test]
+    fn full_rows_are_cleared_and_rows_above_collapse() {
+        let mut board = Board::default();
+        fill_row(&mut board, 0);
+        board.set(4, 1, Cell::Filled(TetrominoKind::T));
+        fill_row(&mut board, 2);
+        board.set(7, 3, Cell::Filled(TetrominoKind::I));
+
+        let cleared = board.clear_full_rows();
+
+        assert_eq!(cleared.rows, vec![0, 2]);
+        assert_eq!(cleared.count(), 2);
+        assert_eq!(board.get(4, 0), Some(Cell::Filled(TetrominoKind::T)));
+        assert_eq!(board.get(7, 1), Some(Cell::Filled(TetrominoKind::I)));
+        assert!((2..board.height()).all(|y| board.row(y).iter().all(|&cell| cell == Cell::Empty)));
+    }
+
+    #[test]
+    fn rows_with_a_gap_stay() {
+        let mut board = Board::default();
+        fill_row(&mut board, 0);
+        board.set(5, 0, Cell::Empty);
+
+        assert!(board.clear_full_rows().is_empty());
+        assert_eq!(board.get(0, 0), Some(Cell::Garbage));
+    }
+
+    #[test]
+    fn garbage_rises_under_the_stack_with_a_hole() {
+        let mut board = Board::default();
+        board.set(2, 0, Cell::Filled(TetrominoKind::O));
+
+        assert!(board.push_garbage(2, 7));
+
+        for y in 0..2 {
+            assert_eq!(board.get(7, y), Some(Cell::Empty));
+            assert_eq!(board.get(0, y), Some(Cell::Garbage));
         }
+        assert_eq!(board.get(2, 2), Some(Cell::Filled(TetrominoKind::O)));
+    }
+
+    #[test]
+    #[should_panic(expected = "garbage hole")]
+    fn garbage_hole_must_be_on_the_board() {
+        Board::default().push_garbage(1, BOARD_WIDTH);
     }
 }
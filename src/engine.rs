@@ -0,0 +1,961 @@
+use crate::board::{
+    BOARD_WIDTH, Board, Cell, LineClear, MIN_BOARD_WIDTH, MIN_VISIBLE_HEIGHT, VISIBLE_HEIGHT,
+};
+use crate::randomizer::PieceRandomizer;
+use crate::scoring::{Score, TSpin};
+use crate::tetrominoes::{ActivePiece, Rotation, TetrominoKind, can_place, kicks_for, lock_piece};
+use serde::{Deserialize, Serialize};
+
+/// Index of the fifth SRS kick test, which always makes a T-spin a full one.
+const SRS_LAST_KICK: usize = 4;
+
+/// Lines needed to advance one level.
+pub const LINES_PER_LEVEL: u32 = 10;
+
+/// At this level the guideline curve passes 20G, so it is capped there and stops speeding up.
+const MAX_GRAVITY_LEVEL: u32 = 19;
+
+/// Gravity is measured in rows per tick with this many steps to a row, so the whole simulation
+/// runs on integers and a replay plays out identically on every machine.
+pub const GRAVITY_UNIT: u32 = 1 << 16;
+
+/// The guideline's top speed: 20 rows a tick, which drops a piece straight to the stack.
+pub const MAX_GRAVITY: u32 = 20 * GRAVITY_UNIT;
+
+/// [`GRAVITY_UNIT`]s per tick for levels 1 to [`MAX_GRAVITY_LEVEL`], precomputed from the
+/// guideline curve of `(0.8 - (level - 1) * 0.007) ^ (level - 1)` seconds per row and rounded
+/// up, so level 1 falls exactly one row every [`TICK_RATE`] ticks. The last level is capped at
+/// [`MAX_GRAVITY`].
+const GRAVITY_TABLE: [u32; MAX_GRAVITY_LEVEL as usize] = [
+    1093, 1378, 1769, 2311, 3076, 4169, 5759, 8107, 11635, 17027, 25416, 38709, 60169, 95484,
+    154743, 256187, 433425, 749597, 1310720,
+];
+
+/// Gravity at `level` in [`GRAVITY_UNIT`]s per tick. High levels fall several rows per tick.
+pub fn gravity(level: u32) -> u32 {
+    GRAVITY_TABLE[(level.clamp(1, MAX_GRAVITY_LEVEL) - 1) as usize]
+}
+
+/// Simulation ticks per second. Live play and replays both advance one [`GameState::tick`] at a
+/// time and keep every timer in whole ticks, so a game plays out the same whatever the frame
+/// rate.
+pub const TICK_RATE: u32 = 60;
+
+/// Seconds per simulation tick.
+pub const TICK: f32 = 1.0 / TICK_RATE as f32;
+
+/// Ticks a grounded piece waits before locking: half a second, per the guideline.
+pub const LOCK_DELAY: u32 = 30;
+
+/// Guideline cap on lock delay resets for a single piece.
+pub const MAX_LOCK_RESETS: u32 = 15;
+
+/// Default delayed auto shift: ticks a direction must be held before it starts repeating.
+pub const DAS: u32 = 10;
+
+/// Default auto repeat rate: ticks between repeated shifts once DAS has charged.
+pub const ARR: u32 = 2;
+
+/// Guideline soft drop speed, as a multiple of normal gravity.
+pub const SOFT_DROP_FACTOR: u32 = 20;
+
+/// Whole ticks closest to `ms` milliseconds.
+pub fn ms_to_ticks(ms: f32) -> u32 {
+    (ms.max(0.0) * TICK_RATE as f32 / 1000.0).round() as u32
+}
+
+/// Milliseconds lasted by `ticks` ticks.
+pub fn ticks_to_ms(ticks: u32) -> f32 {
+    ticks as f32 * 1000.0 / TICK_RATE as f32
+}
+
+/// What holding [`Action::SoftDrop`] does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SoftDrop {
+    /// Fall this many times faster than normal gravity.
+    Factor(u32),
+    /// Fall straight to the floor, but leave the piece to the normal lock delay.
+    Sonic,
+}
+
+/// How horizontal movement repeats while a direction is held.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handling {
+    /// Delayed auto shift in ticks.
+    pub das: u32,
+    /// Auto repeat rate in ticks; `0` moves the piece straight to the wall.
+    pub arr: u32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self { das: DAS, arr: ARR }
+    }
+}
+
+/// Progress of the held horizontal direction.
+#[derive(Clone, Debug, Default)]
+struct AutoShift {
+    /// -1 for left, 1 for right, 0 when neither is held.
+    dir: i32,
+    /// Ticks the current direction has been held.
+    held_for: u32,
+    /// Ticks banked towards the next repeat once DAS has charged.
+    repeat_timer: u32,
+}
+
+/// How many times moving or rotating a grounded piece may restart its lock delay.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockResets {
+    /// At most this many resets; the count starts over when the piece falls below its lowest row.
+    Limited(u32),
+    /// Every successful move or rotation restarts the delay ("infinity").
+    Infinite,
+}
+
+/// Tunable rules for a game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameConfig {
+    /// Ticks a piece may rest on the stack before it locks.
+    pub lock_delay: u32,
+    pub lock_resets: LockResets,
+    pub handling: Handling,
+    pub soft_drop: SoftDrop,
+    pub start_level: u32,
+    pub lines_per_level: u32,
+    /// Columns of the board.
+    pub board_width: usize,
+    /// Rows of the board shown on screen; the hidden buffer is added on top.
+    pub visible_height: usize,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            lock_delay: LOCK_DELAY,
+            lock_resets: LockResets::Limited(MAX_LOCK_RESETS),
+            handling: Handling::default(),
+            soft_drop: SoftDrop::Factor(SOFT_DROP_FACTOR),
+            start_level: 1,
+            lines_per_level: LINES_PER_LEVEL,
+            board_width: BOARD_WIDTH,
+            visible_height: VISIBLE_HEIGHT,
+        }
+    }
+}
+
+/// Something the player asked the game to do this step.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    SoftDrop,
+    HardDrop,
+    Hold,
+}
+
+/// Player input for a single call to [`GameState::tick`].
+#[derive(Clone, Debug, Default)]
+pub struct Inputs {
+    /// Actions whose key went down since the previous tick.
+    pub pressed: Vec<Action>,
+    /// Actions whose key is down right now, including the ones just pressed.
+    pub held: Vec<Action>,
+}
+
+impl Inputs {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+}
+
+/// Things that happened during a tick, for the presentation layer to react to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    PieceSpawned(TetrominoKind),
+    PieceLocked,
+    PieceHeld(TetrominoKind),
+    LinesCleared(LineClear),
+    LevelUp(u32),
+    /// A T piece locked with a T-spin, clearing `lines` rows.
+    TSpin { spin: TSpin, lines: u32 },
+    GameOver(TopOut),
+}
+
+/// Why a game ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopOut {
+    /// A new piece spawned overlapping the stack.
+    BlockOut,
+    /// A piece locked entirely above the visible playfield.
+    LockOut,
+    /// Incoming garbage pushed the stack past the top of the buffer.
+    GarbageOut,
+}
+
+/// Everything needed to play one game, driven purely by [`GameState::tick`].
+#[derive(Clone, Debug)]
+pub struct GameState {
+    pub config: GameConfig,
+    pub board: Board,
+    pub active: ActivePiece,
+    /// Deals the active piece and the upcoming queue.
+    pub randomizer: PieceRandomizer,
+    /// Kind put aside with [`Action::Hold`], if any.
+    pub held: Option<TetrominoKind>,
+    /// Cleared by a hold and restored by the next lock, so each piece can be held only once.
+    hold_available: bool,
+    pub score: Score,
+    pub lines: u32,
+    pub level: u32,
+    /// Ticks played, not counting time spent paused.
+    pub ticks: u32,
+    /// [`GRAVITY_UNIT`]s per tick at the current level.
+    pub gravity: u32,
+    /// Progress towards the next row of gravity, in [`GRAVITY_UNIT`]s.
+    gravity_progress: u32,
+    /// Ticks the active piece has been resting on the stack.
+    lock_timer: u32,
+    /// Whether the piece has been lifted off the stack since its lock delay started.
+    lifted: bool,
+    /// Lock delay resets used since the piece last reached a new lowest row.
+    lock_resets: u32,
+    lowest_y: i32,
+    auto_shift: AutoShift,
+    /// The last rotation and the index of the kick it used, while rotating is the last thing
+    /// the active piece did. Any later move clears it.
+    last_rotation: Option<(Rotation, usize)>,
+    game_over: Option<TopOut>,
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new(PieceRandomizer::from_entropy())
+    }
+}
+
+impl GameState {
+    pub fn new(randomizer: PieceRandomizer) -> Self {
+        Self::with_config(randomizer, GameConfig::default())
+    }
+
+    /// A new game dealt by `randomizer`. Board sizes below [`MIN_BOARD_WIDTH`] and
+    /// [`MIN_VISIBLE_HEIGHT`] are raised to them.
+    pub fn with_config(mut randomizer: PieceRandomizer, mut config: GameConfig) -> Self {
+        config.board_width = config.board_width.max(MIN_BOARD_WIDTH);
+        config.visible_height = config.visible_height.max(MIN_VISIBLE_HEIGHT);
+        let board = Board::new(config.board_width, config.visible_height);
+        let kind = randomizer.next_kind();
+        let active = ActivePiece::spawn_new(kind, &board);
+        let level = config.start_level.max(1);
+        let mut game = Self {
+            config,
+            board,
+            lowest_y: active.y,
+            active,
+            randomizer,
+            held: None,
+            hold_available: true,
+            score: Score::default(),
+            lines: 0,
+            level,
+            ticks: 0,
+            gravity: gravity(level),
+            gravity_progress: 0,
+            lock_timer: 0,
+            lifted: false,
+            lock_resets: 0,
+            auto_shift: AutoShift::default(),
+            last_rotation: None,
+            game_over: None,
+        };
+        game.spawn(kind, &mut Vec::new());
+        game
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over.is_some()
+    }
+
+    /// The reason the game ended, once it has.
+    pub fn top_out(&self) -> Option<TopOut> {
+        self.game_over
+    }
+
+    /// Seconds played, not counting time spent paused.
+    pub fn time(&self) -> f32 {
+        self.ticks as f32 / TICK_RATE as f32
+    }
+
+    pub fn can_hold(&self) -> bool {
+        self.hold_available
+    }
+
+    /// Push `rows` garbage rows with a hole at column `hole` under the stack. The active piece
+    /// is lifted clear if the rising stack runs into it. Panics if `hole` is not a column of
+    /// the board.
+    pub fn add_garbage(&mut self, rows: usize, hole: usize) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        if self.is_game_over() || rows == 0 {
+            return events;
+        }
+        if !self.board.push_garbage(rows, hole) {
+            self.end(TopOut::GarbageOut, &mut events);
+            return events;
+        }
+        while !can_place(&self.active, &self.board) {
+            if self.active.y + 1 >= self.board.height() as i32 {
+                self.end(TopOut::GarbageOut, &mut events);
+                return events;
+            }
+            self.active.y += 1;
+        }
+        events
+    }
+
+    /// The active piece moved to where a hard drop would land it.
+    pub fn ghost(&self) -> ActivePiece {
+        let mut ghost = self.active.clone();
+        while can_place(&ghost.moved(0, -1), &self.board) {
+            ghost.y -= 1;
+        }
+        ghost
+    }
+
+    /// Advance the game by one tick, applying `inputs` first, then gravity and lock delay.
+    pub fn tick(&mut self, inputs: &Inputs) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        if self.is_game_over() {
+            return events;
+        }
+        self.ticks += 1;
+
+        if inputs.pressed(Action::Hold) {
+            self.hold(&mut events);
+        }
+        if self.is_game_over() {
+            return events;
+        }
+
+        let mut moved = self.auto_shift(inputs);
+        if inputs.pressed(Action::RotateCw) {
+            moved |= self.try_rotate(Rotation::Cw);
+        }
+        if inputs.pressed(Action::RotateCcw) {
+            moved |= self.try_rotate(Rotation::Ccw);
+        }
+        if inputs.pressed(Action::Rotate180) {
+            moved |= self.try_rotate(Rotation::Half);
+        }
+        if moved {
+            self.reset_lock_delay();
+        }
+        if inputs.pressed(Action::HardDrop) {
+            let ghost = self.ghost();
+            let rows = (self.active.y - ghost.y) as u32;
+            if rows > 0 {
+                self.score.hard_drop(rows);
+                self.last_rotation = None;
+            }
+            self.active = ghost;
+            self.lock_and_spawn(&mut events);
+            return events;
+        }
+
+        self.apply_gravity(inputs.held(Action::SoftDrop));
+        self.update_lock_delay(&mut events);
+
+        events
+    }
+
+    /// Horizontal movement: one shift on press, then DAS and ARR while held. When both
+    /// directions are held the most recently pressed one wins. Returns whether the piece moved.
+    fn auto_shift(&mut self, inputs: &Inputs) -> bool {
+        let left = inputs.held(Action::MoveLeft);
+        let right = inputs.held(Action::MoveRight);
+
+        let pressed_dir = if inputs.pressed(Action::MoveRight) {
+            1
+        } else if inputs.pressed(Action::MoveLeft) {
+            -1
+        } else {
+            0
+        };
+        if pressed_dir != 0 {
+            self.auto_shift = AutoShift {
+                dir: pressed_dir,
+                ..AutoShift::default()
+            };
+            return self.try_move(pressed_dir, 0);
+        }
+
+        let still_held = match self.auto_shift.dir {
+            -1 => left,
+            1 => right,
+            _ => false,
+        };
+        if !still_held {
+            // Fall back to the other direction if it is still held, charging DAS afresh.
+            let dir = if left {
+                -1
+            } else if right {
+                1
+            } else {
+                0
+            };
+            self.auto_shift = AutoShift {
+                dir,
+                ..AutoShift::default()
+            };
+            return false;
+        }
+
+        let Handling { das, arr } = self.config.handling;
+        let dir = self.auto_shift.dir;
+        let was_charged = self.auto_shift.held_for >= das;
+        self.auto_shift.held_for = self.auto_shift.held_for.saturating_add(1);
+        if self.auto_shift.held_for < das {
+            return false;
+        }
+
+        if arr == 0 {
+            let mut moved = false;
+            while self.try_move(dir, 0) {
+                moved = true;
+            }
+            return moved;
+        }
+
+        let mut moved = false;
+        if was_charged {
+            self.auto_shift.repeat_timer += 1;
+        } else {
+            // DAS just charged: shift now and start repeating from the leftover ticks.
+            moved |= self.try_move(dir, 0);
+            self.auto_shift.repeat_timer = self.auto_shift.held_for - das;
+        }
+        while self.auto_shift.repeat_timer >= arr {
+            self.auto_shift.repeat_timer -= arr;
+            moved |= self.try_move(dir, 0);
+        }
+        moved
+    }
+
+    fn is_grounded(&self) -> bool {
+        !can_place(&self.active.moved(0, -1), &self.board)
+    }
+
+    /// Let the piece fall, faster while soft drop is held. Soft-dropped rows score points.
+    fn apply_gravity(&mut self, soft_drop: bool) {
+        let gravity = match (soft_drop, self.config.soft_drop) {
+            (true, SoftDrop::Sonic) => {
+                while self.fall() {
+                    self.score.soft_drop(1);
+                }
+                self.gravity_progress = 0;
+                return;
+            }
+            (true, SoftDrop::Factor(factor)) => self.gravity.saturating_mul(factor.max(1)),
+            (false, _) => self.gravity,
+        };
+
+        self.gravity_progress = self.gravity_progress.saturating_add(gravity);
+        while self.gravity_progress >= GRAVITY_UNIT {
+            self.gravity_progress -= GRAVITY_UNIT;
+            if !self.fall() {
+                // Resting pieces don't bank gravity for when they slide off a ledge.
+                self.gravity_progress = 0;
+                break;
+            }
+            if soft_drop {
+                self.score.soft_drop(1);
+            }
+        }
+    }
+
+    /// Move the piece down one row. Reaching a new lowest row restarts the lock delay and gives
+    /// back all lock delay resets.
+    fn fall(&mut self) -> bool {
+        if !self.try_move(0, -1) {
+            return false;
+        }
+        if self.active.y < self.lowest_y {
+            self.lowest_y = self.active.y;
+            self.lock_resets = 0;
+            self.lock_timer = 0;
+            self.lifted = false;
+        }
+        true
+    }
+
+    /// Run the lock delay while the piece rests on the stack and lock it once it expires. The
+    /// delay pauses while the piece is lifted off the stack; once the resets are used up, a
+    /// piece that comes back down locks straight away.
+    fn update_lock_delay(&mut self, events: &mut Vec<GameEvent>) {
+        if !self.is_grounded() {
+            self.lifted |= self.lock_timer > 0;
+            return;
+        }
+        let landed_again = std::mem::take(&mut self.lifted);
+        self.lock_timer += 1;
+        if self.lock_timer >= self.config.lock_delay || (landed_again && !self.has_lock_resets()) {
+            self.lock_and_spawn(events);
+        }
+    }
+
+    fn has_lock_resets(&self) -> bool {
+        match self.config.lock_resets {
+            LockResets::Limited(max) => self.lock_resets < max,
+            LockResets::Infinite => true,
+        }
+    }
+
+    /// A successful move or rotation of a resting piece restarts its lock delay, until the
+    /// piece runs out of resets.
+    fn reset_lock_delay(&mut self) {
+        if self.lock_timer == 0 || !self.has_lock_resets() {
+            return;
+        }
+        if matches!(self.config.lock_resets, LockResets::Limited(_)) {
+            self.lock_resets += 1;
+        }
+        self.lock_timer = 0;
+        self.lifted = false;
+    }
+
+    /// Shift the active piece if the target position is free. Returns whether it moved.
+    fn try_move(&mut self, dx: i32, dy: i32) -> bool {
+        let moved = self.active.moved(dx, dy);
+        if !can_place(&moved, &self.board) {
+            return false;
+        }
+        self.active = moved;
+        self.last_rotation = None;
+        true
+    }
+
+    /// Turn the active piece, trying each SRS kick offset in order. Returns whether it turned.
+    fn try_rotate(&mut self, rotation: Rotation) -> bool {
+        let from = self.active.rotation;
+        let rotated = self.active.rotated(rotation.apply(from));
+        let kicks = kicks_for(self.active.kind, from, rotation);
+        for (kick, &(dx, dy)) in kicks.iter().enumerate() {
+            let kicked = rotated.moved(dx, dy);
+            if can_place(&kicked, &self.board) {
+                self.active = kicked;
+                self.last_rotation = Some((rotation, kick));
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Classify the active piece as a T-spin with the 3-corner rule: a T locked right after a
+    /// rotation with at least three of the four corners around its centre blocked (walls and
+    /// floor count) is a T-spin. It is a full one if both corners beside the pointing side are
+    /// blocked, or if the rotation needed the last SRS kick; otherwise a mini.
+    fn t_spin(&self) -> TSpin {
+        let Some((rotation, kick)) = self.last_rotation else {
+            return TSpin::None;
+        };
+        if self.active.kind != TetrominoKind::T {
+            return TSpin::None;
+        }
+
+        let (cx, cy) = (self.active.x + 1, self.active.y + 1);
+        let blocked = |(dx, dy): (i32, i32)| {
+            let (x, y) = (cx + dx, cy + dy);
+            x < 0
+                || x >= self.board.width() as i32
+                || y < 0
+                || self.board.get(x, y).is_some_and(Cell::is_filled)
+        };
+        // Corners beside the side the T points to, then the two behind it.
+        let (front, back) = match self.active.rotation {
+            0 => ([(-1, 1), (1, 1)], [(-1, -1), (1, -1)]),
+            1 => ([(1, 1), (1, -1)], [(-1, 1), (-1, -1)]),
+            2 => ([(-1, -1), (1, -1)], [(-1, 1), (1, 1)]),
+            _ => ([(-1, 1), (-1, -1)], [(1, 1), (1, -1)]),
+        };
+        let front_blocked = front.into_iter().filter(|&corner| blocked(corner)).count();
+        let back_blocked = back.into_iter().filter(|&corner| blocked(corner)).count();
+
+        if front_blocked + back_blocked < 3 {
+            TSpin::None
+        } else if front_blocked == 2 || (rotation != Rotation::Half && kick == SRS_LAST_KICK) {
+            TSpin::Full
+        } else {
+            TSpin::Mini
+        }
+    }
+
+    /// Swap the active piece with the held one (or the next in the queue if nothing is held yet)
+    /// and restart it from the spawn position.
+    fn hold(&mut self, events: &mut Vec<GameEvent>) {
+        if !self.hold_available {
+            return;
+        }
+        self.hold_available = false;
+
+        let current = self.active.kind;
+        let next = match self.held.replace(current) {
+            Some(kind) => kind,
+            None => self.randomizer.next_kind(),
+        };
+        events.push(GameEvent::PieceHeld(current));
+        self.spawn(next, events);
+    }
+
+    /// Write the active piece into the stack, clear completed rows and bring in the next piece.
+    fn lock_and_spawn(&mut self, events: &mut Vec<GameEvent>) {
+        let spin = self.t_spin();
+        lock_piece(&self.active, &mut self.board);
+        events.push(GameEvent::PieceLocked);
+
+        let visible_height = self.board.visible_height() as i32;
+        if self.active.cells().all(|(_, y)| y >= visible_height) {
+            self.end(TopOut::LockOut, events);
+            return;
+        }
+
+        let cleared = self.board.clear_full_rows();
+        self.score.lock(cleared.count(), spin, self.level);
+        if spin != TSpin::None {
+            events.push(GameEvent::TSpin {
+                spin,
+                lines: cleared.count(),
+            });
+        }
+        if !cleared.is_empty() {
+            self.lines += cleared.count();
+            events.push(GameEvent::LinesCleared(cleared));
+            self.update_level(events);
+        }
+
+        self.hold_available = true;
+        let kind = self.randomizer.next_kind();
+        self.spawn(kind, events);
+    }
+
+    fn update_level(&mut self, events: &mut Vec<GameEvent>) {
+        let level =
+            self.config.start_level.max(1) + self.lines / self.config.lines_per_level.max(1);
+        if level > self.level {
+            self.level = level;
+            self.gravity = gravity(level);
+            events.push(GameEvent::LevelUp(level));
+        }
+    }
+
+    fn spawn(&mut self, kind: TetrominoKind, events: &mut Vec<GameEvent>) {
+        self.active = ActivePiece::spawn_new(kind, &self.board);
+        self.gravity_progress = 0;
+        self.lock_timer = 0;
+        self.lifted = false;
+        self.lock_resets = 0;
+        self.lowest_y = self.active.y;
+        self.last_rotation = None;
+
+        // A fresh piece that already overlaps the stack ends the game.
+        if !can_place(&self.active, &self.board) {
+            self.end(TopOut::BlockOut, events);
+            return;
+        }
+        self.drop_into_view();
+        events.push(GameEvent::PieceSpawned(kind));
+    }
+
+    /// Pieces spawn just above the visible field and, per the guideline, drop one row straight
+    /// away if nothing is in the way, so the player sees them from the first tick.
+    fn drop_into_view(&mut self) {
+        self.fall();
+    }
+
+    fn end(&mut self, reason: TopOut, events: &mut Vec<GameEvent>) {
+        self.game_over = Some(reason);
+        events.push(GameEvent::GameOver(reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game whose active piece is a T resting flat on the floor of an empty board.
+    fn t_on_floor(lock_resets: LockResets) -> GameState {
+        let config = GameConfig {
+            lock_resets,
+            ..GameConfig::default()
+        };
+        let mut game = GameState::with_config(PieceRandomizer::new(1), config);
+        game.active = ActivePiece {
+            kind: TetrominoKind::T,
+            rotation: 0,
+            x: 3,
+            y: -1,
+        };
+        game.lowest_y = game.active.y;
+        game
+    }
+
+    /// A T pointing up on the floor at column 3, just turned with the kick at index `kick`,
+    /// with `corners` of the board around its centre filled in.
+    fn spun_t(rotation: Rotation, kick: usize, corners: &[(i32, i32)]) -> GameState {
+        let mut game = t_on_floor(LockResets::Infinite);
+        game.active.y = 0;
+        for &(x, y) in corners {
+            game.board.set(x, y, Cell::Garbage);
+        }
+        game.last_rotation = Some((rotation, kick));
+        game
+    }
+
+    #[test]
+    fn three_blocked_corners_make_a_t_spin() {
+        // Centre (4, 1): front corners (3, 2) and (5, 2), back corners (3, 0) and (5, 0).
+        let full = spun_t(Rotation::Cw, 0, &[(3, 2), (5, 2), (3, 0)]);
+        assert_eq!(full.t_spin(), TSpin::Full);
+
+        let mini = spun_t(Rotation::Cw, 0, &[(3, 2), (3, 0), (5, 0)]);
+        assert_eq!(mini.t_spin(), TSpin::Mini);
+
+        let none = spun_t(Rotation::Cw, 0, &[(3, 0), (5, 0)]);
+        assert_eq!(none.t_spin(), TSpin::None);
+    }
+
+    #[test]
+    fn walls_and_floor_count_as_blocked_corners() {
+        let mut game = spun_t(Rotation::Ccw, 0, &[(1, 2)]);
+        game.active.x = -1;
+        game.active.rotation = 1;
+        // Centre (0, 1): the wall blocks both corners on the left, the stack one front corner.
+        assert_eq!(game.t_spin(), TSpin::Mini);
+
+        game.board.set(1, 0, Cell::Garbage);
+        assert_eq!(game.t_spin(), TSpin::Full);
+    }
+
+    #[test]
+    fn last_srs_kick_upgrades_a_mini_to_a_full_t_spin() {
+        let corners = [(3, 2), (3, 0), (5, 0)];
+        let spin = |rotation| spun_t(rotation, SRS_LAST_KICK, &corners).t_spin();
+        assert_eq!(spin(Rotation::Cw), TSpin::Full);
+        assert_eq!(spin(Rotation::Ccw), TSpin::Full);
+        // 180° turns use a different kick table, so their fifth kick is nothing special.
+        assert_eq!(spin(Rotation::Half), TSpin::Mini);
+    }
+
+    #[test]
+    fn only_a_t_that_rotated_last_can_spin() {
+        let corners = [(3, 2), (5, 2), (3, 0)];
+        let mut game = spun_t(Rotation::Cw, 0, &corners);
+        game.last_rotation = None;
+        assert_eq!(game.t_spin(), TSpin::None);
+
+        let mut game = spun_t(Rotation::Cw, 0, &corners);
+        game.active.kind = TetrominoKind::S;
+        assert_eq!(game.t_spin(), TSpin::None);
+    }
+
+    #[test]
+    fn spawned_pieces_show_in_the_top_visible_row() {
+        let board = Board::default();
+        for kind in TetrominoKind::ALL {
+            let mut game = GameState::new(PieceRandomizer::new(5));
+            let mut events = Vec::new();
+            game.spawn(kind, &mut events);
+            assert_eq!(events, vec![GameEvent::PieceSpawned(kind)]);
+            let lowest = game.active.cells().map(|(_, y)| y).min().unwrap();
+            assert_eq!(lowest, board.visible_height() as i32 - 1, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn first_piece_shows_in_the_top_visible_row() {
+        let game = GameState::new(PieceRandomizer::new(5));
+        let lowest = game.active.cells().map(|(_, y)| y).min().unwrap();
+        assert_eq!(lowest, VISIBLE_HEIGHT as i32 - 1);
+    }
+
+    fn hard_drop() -> Inputs {
+        Inputs {
+            pressed: vec![Action::HardDrop],
+            held: vec![Action::HardDrop],
+        }
+    }
+
+    #[test]
+    fn spawning_into_the_stack_is_a_block_out() {
+        let mut game = t_on_floor(LockResets::Infinite);
+        game.active.x = 0;
+        let spawn_rows = VISIBLE_HEIGHT as i32..VISIBLE_HEIGHT as i32 + 2;
+        for (x, y) in (3..7).flat_map(|x| spawn_rows.clone().map(move |y| (x, y))) {
+            game.board.set(x, y, Cell::Garbage);
+        }
+
+        let events = game.tick(&hard_drop());
+
+        assert!(events.contains(&GameEvent::GameOver(TopOut::BlockOut)));
+        assert_eq!(game.top_out(), Some(TopOut::BlockOut));
+    }
+
+    #[test]
+    fn locking_above_the_visible_field_is_a_lock_out() {
+        let mut game = GameState::new(PieceRandomizer::new(8));
+        for y in 0..VISIBLE_HEIGHT as i32 {
+            for x in 0..BOARD_WIDTH as i32 - 1 {
+                game.board.set(x, y, Cell::Garbage);
+            }
+        }
+        game.active = ActivePiece {
+            kind: TetrominoKind::O,
+            rotation: 0,
+            x: 3,
+            y: VISIBLE_HEIGHT as i32,
+        };
+
+        let events = game.tick(&hard_drop());
+
+        assert!(events.contains(&GameEvent::GameOver(TopOut::LockOut)));
+        assert_eq!(game.top_out(), Some(TopOut::LockOut));
+    }
+
+    #[test]
+    fn garbage_past_the_top_is_a_garbage_out() {
+        let mut game = GameState::new(PieceRandomizer::new(8));
+        game.board.set(0, 0, Cell::Garbage);
+
+        let events = game.add_garbage(game.board.height(), 4);
+
+        assert_eq!(events, vec![GameEvent::GameOver(TopOut::GarbageOut)]);
+        assert_eq!(game.top_out(), Some(TopOut::GarbageOut));
+        assert!(game.tick(&hard_drop()).is_empty());
+    }
+
+    #[test]
+    fn garbage_lifts_the_active_piece() {
+        let mut game = t_on_floor(LockResets::Infinite);
+
+        assert!(game.add_garbage(2, 0).is_empty());
+
+        assert_eq!(game.active.y, 1);
+        assert!(can_place(&game.active, &game.board));
+        assert_eq!(game.board.get(0, 1), Some(Cell::Empty));
+        assert_eq!(game.board.get(1, 1), Some(Cell::Garbage));
+        assert!(!game.is_game_over());
+    }
+
+    fn sized(board_width: usize, visible_height: usize) -> GameState {
+        let config = GameConfig {
+            board_width,
+            visible_height,
+            ..GameConfig::default()
+        };
+        GameState::with_config(PieceRandomizer::new(11), config)
+    }
+
+    #[test]
+    fn pieces_spawn_centred_on_other_board_widths() {
+        let game = sized(12, 20);
+        assert!(game.active.cells().all(|(x, _)| (4..=7).contains(&x)));
+
+        let mut game = sized(4, 20);
+        game.active = ActivePiece::spawn_new(TetrominoKind::I, &game.board);
+        assert_eq!(game.active.x, 0);
+        assert!(can_place(&game.active, &game.board));
+    }
+
+    #[test]
+    fn a_flat_i_clears_a_line_on_a_four_wide_board() {
+        let mut game = sized(4, 20);
+        game.active = ActivePiece::spawn_new(TetrominoKind::I, &game.board);
+
+        let events = game.tick(&hard_drop());
+
+        assert!(events.contains(&GameEvent::LinesCleared(LineClear { rows: vec![0] })));
+        assert_eq!(game.lines, 1);
+        assert!(!game.is_game_over());
+        assert!((0..4).all(|x| game.board.get(x, 0) == Some(Cell::Empty)));
+    }
+
+    #[test]
+    fn boards_too_small_to_play_on_are_raised_to_the_minimum() {
+        for width in [0, 2] {
+            let mut game = sized(width, 20);
+            assert_eq!(game.board.width(), MIN_BOARD_WIDTH);
+            assert_eq!(game.config.board_width, MIN_BOARD_WIDTH);
+            for _ in 0..60 {
+                game.tick(&Inputs::default());
+            }
+            assert_eq!(game.top_out(), None, "{width} wide");
+        }
+
+        let game = sized(10, 0);
+        assert_eq!(game.board.visible_height(), MIN_VISIBLE_HEIGHT);
+        assert_eq!(game.config.visible_height, MIN_VISIBLE_HEIGHT);
+        assert!(!game.is_game_over());
+    }
+
+    #[test]
+    fn gravity_speeds_up_with_level_and_caps() {
+        assert_eq!(gravity(0), gravity(1));
+        // Level 1 falls one row a second.
+        assert_eq!(GRAVITY_UNIT.div_ceil(gravity(1)), TICK_RATE);
+        assert!(GRAVITY_TABLE.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(gravity(MAX_GRAVITY_LEVEL), MAX_GRAVITY);
+        assert_eq!(gravity(MAX_GRAVITY_LEVEL + 5), MAX_GRAVITY);
+    }
+
+    #[test]
+    fn piece_falls_one_row_per_second_at_level_one() {
+        let mut game = GameState::new(PieceRandomizer::new(3));
+        let start = game.active.y;
+        for _ in 0..TICK_RATE {
+            game.tick(&Inputs::default());
+        }
+        assert_eq!(game.active.y, start - 1);
+    }
+
+    /// Ticks until the active piece locks while it is turned 180° every ten ticks.
+    fn ticks_to_lock_while_spinning(game: &mut GameState) -> u32 {
+        for tick in 1..=10_000 {
+            let pressed = if tick % 10 == 0 {
+                vec![Action::Rotate180]
+            } else {
+                Vec::new()
+            };
+            let events = game.tick(&Inputs {
+                pressed,
+                held: Vec::new(),
+            });
+            if events.contains(&GameEvent::PieceLocked) {
+                return tick;
+            }
+        }
+        panic!("piece never locked");
+    }
+
+    #[test]
+    fn kicking_upward_does_not_restart_lock_delay_without_resets() {
+        let mut game = t_on_floor(LockResets::Limited(0));
+        assert_eq!(ticks_to_lock_while_spinning(&mut game), 30);
+    }
+
+    #[test]
+    fn lock_resets_are_capped() {
+        let mut game = t_on_floor(LockResets::Limited(MAX_LOCK_RESETS));
+        // Each reset can buy at most one more full lock delay.
+        let ticks = ticks_to_lock_while_spinning(&mut game);
+        assert!(
+            ticks <= (MAX_LOCK_RESETS + 1) * LOCK_DELAY,
+            "locked after {ticks} ticks"
+        );
+    }
+}
@@ -1,14 +1,81 @@
 use crate::MenuCamera;
+use crate::highscores::{HighScores, InitialsEntry, spawn_high_score_area};
+use crate::input::{ActionState, InputAction, InputQueue};
+use crate::hud::{
+    AnnouncementText, HUD_MARGIN, HUD_WIDTH, LevelText, PreviewConfig, ScoreText,
+    spawn_hold_slot, spawn_next_queue,
+};
 use crate::menu::{StartButton, QuitButton};
-use crate::{BOARD_HEIGHT, BOARD_WIDTH, Board, Cell};
-use bevy::input::ButtonInput;
 use bevy::prelude::*;
 use bevy::window::Window;
-use crate::tetrominoes::{ActivePiece, place_active_on_board, clear_active_from_board, shape_of};
+use serde::{Deserialize, Serialize};
 use crate::states::AppState;
+use crate::palette::Palette;
+use crate::replays::{ReplayRecording, save_replay};
+use tetris::board::Board;
+use tetris::engine::{GameConfig, GameEvent, GameState, TopOut};
+use tetris::randomizer::PieceRandomizer;
+
+/// The running game. All rules live in [`GameState`]; the systems below only adapt it to Bevy.
+#[derive(Resource, Deref, DerefMut)]
+pub struct Game(pub GameState);
+
+/// Which kind of game is played. Each mode keeps its own high score table.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum GameMode {
+    /// Endless play, levelling up every ten lines.
+    #[default]
+    Marathon,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 1] = [GameMode::Marathon];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+        }
+    }
+}
+
+/// The mode and rules new games start with, board size included.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct GameRules {
+    pub mode: GameMode,
+    #[deref]
+    pub config: GameConfig,
+}
+
+impl GameRules {
+    pub fn new_game(&self) -> GameState {
+        GameState::with_config(PieceRandomizer::from_entropy(), self.config.clone())
+    }
+}
+
+/// A [`GameEvent`] produced by the engine this frame.
+#[derive(Message, Clone, Debug)]
+pub struct GameEventMessage(pub GameEvent);
 
+/// Whether to show the ghost piece, and how opaque it is (0.0 to 1.0).
 #[derive(Resource)]
-pub struct FallTimer(pub Timer);
+pub struct GhostConfig {
+    pub enabled: bool,
+    pub alpha: f32,
+}
+
+impl Default for GhostConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            alpha: 0.3,
+        }
+    }
+}
+
+/// Everything spawned for a game in progress, despawned when a new one starts or we quit to
+/// the menu.
+#[derive(Component)]
+pub struct InGameRoot;
 
 #[derive(Component)]
 pub struct CellSprite {
@@ -16,11 +83,12 @@ pub struct CellSprite {
     y: usize,
 }
 
-//todo: 10x20 grid
 pub fn setup_ingame(
     windows: Query<&Window>,
     mut commands: Commands,
     mut camera_query: Query<&mut Transform, With<MenuCamera>>,
+    preview_config: Res<PreviewConfig>,
+    game: Res<Game>,
 ) {
     if let Ok(mut transform) = camera_query.single_mut() {
         transform.translation = Vec3::new(0.0, 0.0, 0.0);
@@ -29,11 +97,11 @@ pub fn setup_ingame(
         .spawn((Node {
             position_type: PositionType::Absolute,
             top: Val::Percent(25.0),
-            right: Val::Px(20.0),
+            right: Val::Px(HUD_MARGIN),
             width: Val::Auto,
             height: Val::Auto,
             ..default()
-        },))
+        }, InGameRoot))
         .with_children(|parent| {
             parent
                 .spawn(Node {
@@ -43,7 +111,7 @@ pub fn setup_ingame(
                 })
                 .with_children(|col| {
                     col.spawn((Node {
-                        width: Val::Px(220.0),
+                        width: Val::Px(HUD_WIDTH),
                         height: Val::Px(64.0),
                         justify_content: JustifyContent::FlexEnd,
                         align_items: AlignItems::FlexEnd,
@@ -56,10 +124,11 @@ pub fn setup_ingame(
                                     font_size: 40.0,
                                     ..default()
                                 },
+                                ScoreText,
                             ));
                         });
                     col.spawn((Node {
-                        width: Val::Px(220.0),
+                        width: Val::Px(HUD_WIDTH),
                         height: Val::Px(64.0),
                         justify_content: JustifyContent::FlexEnd,
                         align_items: AlignItems::FlexEnd,
@@ -74,8 +143,9 @@ pub fn setup_ingame(
                                 },
                             ));
                         });
+                    spawn_next_queue(col, &preview_config);
                     col.spawn((Node {
-                        width: Val::Px(220.0),
+                        width: Val::Px(HUD_WIDTH),
                         height: Val::Px(64.0),
                         justify_content: JustifyContent::FlexEnd,
                         align_items: AlignItems::FlexEnd,
@@ -88,26 +158,90 @@ pub fn setup_ingame(
                                     font_size: 40.0,
                                     ..default()
                                 },
+                                LevelText,
                             ));
                         });
+                    col.spawn((Node {
+                        width: Val::Px(HUD_WIDTH),
+                        height: Val::Px(64.0),
+                        justify_content: JustifyContent::FlexEnd,
+                        align_items: AlignItems::FlexEnd,
+                        ..default()
+                    },))
+                        .with_children(|col| {
+                            col.spawn((
+                                Text::new("Hold:  "),
+                                TextFont {
+                                    font_size: 40.0,
+                                    ..default()
+                                },
+                            ));
+                        });
+                    spawn_hold_slot(col);
+                    col.spawn((
+                        Text::new(""),
+                        TextFont {
+                            font_size: 32.0,
+                            ..default()
+                        },
+                        TextColor(Color::srgb(0.8, 0.3, 0.9)),
+                        AnnouncementText,
+                    ));
                 });
         });
     let window = windows
         .single()
         .expect("primary window should exist before entering InGame");
-    let board_width_px = window.width();
-    let board_height_px = window.height();
-    let cell_size =
-        (board_width_px / BOARD_WIDTH as f32).min(board_height_px / BOARD_HEIGHT as f32);
+    spawn_board_cells(&mut commands, window, &game.board);
+}
 
-    let board_pixel_width = cell_size * BOARD_WIDTH as f32;
-    let board_pixel_height = cell_size * BOARD_HEIGHT as f32;
+/// Rebuild the cell sprites when a restarted game comes with a different board size.
+pub fn resize_board_system(
+    windows: Query<&Window>,
+    mut commands: Commands,
+    game: Res<Game>,
+    cells: Query<(Entity, &CellSprite)>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+    let (width, visible_height) = (game.board.width(), game.board.visible_height());
+    let fits = cells.iter().count() == width * visible_height
+        && cells.iter().all(|(_, cell)| cell.x < width && cell.y < visible_height);
+    if fits {
+        return;
+    }
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    for (entity, _) in &cells {
+        commands.entity(entity).despawn();
+    }
+    spawn_board_cells(&mut commands, window, &game.board);
+}
+
+/// One sprite per visible cell of `board`, sized to fit between the left edge of the window
+/// and the HUD.
+fn spawn_board_cells(commands: &mut Commands, window: &Window, board: &Board) {
+    // The board sits `spacing_from_ui` left of centre, so it has to stay clear of the left
+    // edge of the window on one side and of the HUD column on the other.
     let spacing_from_ui = 140.0;
+    let hud_px = HUD_WIDTH + HUD_MARGIN;
+    let board_width_px = (window.width() - 2.0 * spacing_from_ui)
+        .min(window.width() - 2.0 * (hud_px - spacing_from_ui))
+        .max(0.0);
+    let board_height_px = window.height();
+    let (width, visible_height) = (board.width(), board.visible_height());
+    let cell_size = (board_width_px / width as f32).min(board_height_px / visible_height as f32);
+
+    let board_pixel_width = cell_size * width as f32;
+    let board_pixel_height = cell_size * visible_height as f32;
     let offset_x = -board_pixel_width / 2.0 - spacing_from_ui + cell_size / 2.0;
     let offset_y = -board_pixel_height / 2.0 + cell_size / 2.0;
 
-    for y in 0..BOARD_HEIGHT {
-        for x in 0..BOARD_WIDTH {
+    // Only the visible rows get sprites; the buffer above them stays hidden.
+    for y in 0..visible_height {
+        for x in 0..width {
             commands.spawn((
                 Sprite {
                     custom_size: Some(Vec2::splat(cell_size -1.0)),
@@ -120,281 +254,142 @@ pub fn setup_ingame(
                     0.0,
                 ),
                 CellSprite { x, y },
+                InGameRoot,
             ));
         }
     }
 }
 
-pub fn sync_board(board: Res<Board>, mut cells: Query<(&CellSprite, &mut Sprite)>) {
-    if board.is_changed() {
-        for (cell_info, mut sprite) in &mut cells {
-            match board.cells[cell_info.y][cell_info.x] {
-                Cell::Empty => {
-                    sprite.color = Color::srgba(0.0, 0.0, 0.0, 0.7); // faint grid
-                }
-                Cell::Filled(color) => {
-                    sprite.color = color;
-                }
-            }
-        }
-    }
-}
-
-pub fn reset_board(mut board: ResMut<Board>) {
-    for y in 0..BOARD_HEIGHT {
-        for x in 0..BOARD_WIDTH {
-            board.cells[y][x] = Cell::Empty;
-        }
-    }
-}
-
-pub fn spawn_first_piece(
-    mut commands: Commands,
-    mut board: ResMut<Board>,
+/// Compose the locked stack, the ghost and the active piece overlay onto the cell sprites.
+pub fn sync_board(
+    game: Res<Game>,
+    ghost_config: Res<GhostConfig>,
+    palette: Res<Palette>,
+    mut cells: Query<(&CellSprite, &mut Sprite)>,
 ) {
-    let active = ActivePiece::spawn_new();
-
-    // draw it
-    place_active_on_board(&active, &mut board);
-
-    // store as resource
-    commands.insert_resource(active);
-}
-
-pub fn setup_fall_timer(mut commands: Commands) {
-    commands.insert_resource(FallTimer(Timer::from_seconds(0.5, TimerMode::Repeating)));
-}
-
-pub fn hard_drop_system(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut board: ResMut<Board>,
-    mut active: ResMut<ActivePiece>,
-) {
-    // Only act on a fresh Space press
-    if !keyboard_input.just_pressed(KeyCode::Space) {
+    if !game.is_changed() && !ghost_config.is_changed() && !palette.is_changed() {
         return;
     }
 
-    // Remove current piece so it doesn't collide with itself
-    clear_active_from_board(&active, &mut board);
-
-    // Move down as far as possible
-    loop {
-        let shape = shape_of(active.kind.clone(), active.rotation);
-        let mut can_move_down = true;
-
-        for (dx, dy) in shape.cells {
-            let new_x = active.x + dx;
-            let new_y = active.y - 1 + dy;
-
-            // Bottom of board
-            if new_y < 0 {
-                can_move_down = false;
-                break;
-            }
+    // Layer 1: locked cells
+    for (cell_info, mut sprite) in &mut cells {
+        let cell = game.board.get(cell_info.x as i32, cell_info.y as i32);
+        sprite.color = palette.cell(cell.unwrap_or_default());
+    }
 
-            // Collision with existing block
-            if let Some(Cell::Filled(_)) = board.get(new_x, new_y) {
-                can_move_down = false;
-                break;
+    // Layer 2: translucent copy of the active piece where a hard drop would land
+    if ghost_config.enabled {
+        let ghost = game.ghost();
+        let color = palette.piece(game.active.kind).with_alpha(ghost_config.alpha);
+        for (cell_info, mut sprite) in &mut cells {
+            if ghost
+                .cells()
+                .any(|(x, y)| x == cell_info.x as i32 && y == cell_info.y as i32)
+            {
+                sprite.color = color;
             }
         }
+    }
 
-        if can_move_down {
-            active.y -= 1;
-        } else {
-            break;
+    // Layer 3: the falling piece, drawn over everything else
+    for (cell_info, mut sprite) in &mut cells {
+        if game
+            .active
+            .cells()
+            .any(|(x, y)| x == cell_info.x as i32 && y == cell_info.y as i32)
+        {
+            sprite.color = palette.piece(game.active.kind);
         }
     }
-
-    // Lock piece at final position
-    place_active_on_board(&active, &mut board);
-
-    // Spawn a new piece at the top
-    *active = ActivePiece::spawn_new();
-    place_active_on_board(&active, &mut board);
 }
 
-pub fn fall_piece_system(
-    time: Res<Time>,
-    mut timer: ResMut<FallTimer>,
-    mut board: ResMut<Board>,
-    mut active: ResMut<ActivePiece>,
-    mut next_state: ResMut<NextState<AppState>>,
-) {
-    if !timer.0.tick(time.delta()).just_finished() {
-        return;
-    }
-
-    // 1) clear current position FIRST so we don't collide with ourselves
-    clear_active_from_board(&active, &mut board);
-
-    // 2) check if we can move down one cell
-    let shape = shape_of(active.kind.clone(), active.rotation);
-    let mut can_move_down = true;
-
-    for (dx, dy) in shape.cells {
-        let new_x = active.x + dx;
-        let new_y = active.y - 1 + dy;
-
-        // bottom of board
-        if new_y < 0 {
-            can_move_down = false;
-            break;
-        }
-
-        // hit existing block?
-        if let Some(Cell::Filled(_)) = board.get(new_x, new_y) {
-            can_move_down = false;
-            break;
-        }
+/// Despawn the board and HUD left over from the previous game.
+pub fn cleanup_ingame(mut commands: Commands, roots: Query<Entity, With<InGameRoot>>) {
+    for entity in &roots {
+        commands.entity(entity).despawn();
     }
+}
 
-    if can_move_down {
-        // 3a) move down and redraw
-        active.y -= 1;
-        place_active_on_board(&active, &mut board);
-    } else {
-        // 3b) lock in place where it was
-        place_active_on_board(&active, &mut board);
-
-        // 4) spawn a new random piece at the top
-        *active = ActivePiece::spawn_new();
-
-        // GAME OVER CHECK: if the new piece can't be placed (out of bounds or colliding),
-        // switch to GameOver state where we show a big "GAME OVER" sign.
-        if !can_place_piece(&active, &board) {
-            next_state.set(AppState::GameOver);
-            return;
-        }
-
-        // if it's valid, draw it
-        place_active_on_board(&active, &mut board);
-    }
+/// Start a fresh game, and a recording of it, every time we enter `InGame`.
+pub fn start_game(mut commands: Commands, rules: Res<GameRules>, mut queue: ResMut<InputQueue>) {
+    queue.clear();
+    let game = rules.new_game();
+    commands.insert_resource(ReplayRecording::new(&game));
+    commands.insert_resource(Game(game));
 }
 
-/// Move the active tetromino left/right in response to A/D key presses.
-pub fn move_piece_horizontal_system(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut board: ResMut<Board>,
-    mut active: ResMut<ActivePiece>,
+/// Run one simulation tick on the queued input and forward what happened. Runs in
+/// `FixedUpdate` at [`tetris::engine::TICK_RATE`], so the frame rate never changes the outcome.
+pub fn game_tick_system(
+    mut queue: ResMut<InputQueue>,
+    mut game: ResMut<Game>,
+    mut recording: ResMut<ReplayRecording>,
+    mut game_events: MessageWriter<GameEventMessage>,
 ) {
-    // Determine horizontal movement: A = left (-1), D = right (+1)
-    let mut dx = 0;
-    if keyboard_input.just_pressed(KeyCode::KeyA) {
-        dx -= 1;
-    }
-    if keyboard_input.just_pressed(KeyCode::KeyD) {
-        dx += 1;
-    }
-
-    // No horizontal input this frame
-    if dx == 0 {
-        return;
-    }
-
-    // Temporarily clear current piece from the board so it doesn't collide with itself
-    clear_active_from_board(&active, &mut board);
-
-    // Check if we can move horizontally by `dx` without hitting walls or other blocks
-    let shape = shape_of(active.kind.clone(), active.rotation);
-    let mut can_move = true;
-
-    for (cell_dx, cell_dy) in shape.cells {
-        let new_x = active.x + cell_dx + dx;
-        let new_y = active.y + cell_dy;
-
-        // Check board bounds on X and Y
-        if new_x < 0 || new_x >= BOARD_WIDTH as i32 || new_y < 0 {
-            can_move = false;
-            break;
-        }
-
-        // Check collision with existing filled cells
-        if let Some(Cell::Filled(_)) = board.get(new_x, new_y) {
-            can_move = false;
-            break;
-        }
-    }
-
-    // Apply movement if valid
-    if can_move {
-        active.x += dx;
-    }
-
-    // Redraw piece at its (potentially) new position
-    place_active_on_board(&active, &mut board);
+    let inputs = queue.take();
+    recording.record(&game, &inputs);
+    let events = game.tick(&inputs);
+    game_events.write_batch(events.into_iter().map(GameEventMessage));
 }
 
-pub fn rotate_piece_system(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut board: ResMut<Board>,
-    mut active: ResMut<ActivePiece>,
+/// Save the replay of the current game, then throw it away and start over with the same rules.
+pub fn restart_game(
+    game: &mut Game,
+    recording: &mut ReplayRecording,
+    rules: &GameRules,
+    game_events: &mut MessageWriter<GameEventMessage>,
 ) {
-    // Only act on a fresh W key press
-    if !keyboard_input.just_pressed(KeyCode::KeyW) {
-        return;
-    }
-
-    // Remove current piece so it doesn't collide with itself
-    clear_active_from_board(&active, &mut board);
-
-    // Calculate the next rotation state
-    let next_rotation = (active.rotation + 1) % 4;
-    let shape = shape_of(active.kind.clone(), next_rotation);
-    let mut can_rotate = true;
-
-    for (dx, dy) in shape.cells {
-        let new_x = active.x + dx;
-        let new_y = active.y + dy;
-
-        // Check board bounds
-        if new_x < 0 || new_x >= BOARD_WIDTH as i32 || new_y < 0 {
-            can_rotate = false;
-            break;
-        }
-
-        // Check for collisions with existing blocks
-        if let Some(Cell::Filled(_)) = board.get(new_x, new_y) {
-            can_rotate = false;
-            break;
-        }
-    }
+    save_replay(recording, game);
+    game.0 = rules.new_game();
+    *recording = ReplayRecording::new(game);
+    // Let the HUD redraw the queue and hold slot for the fresh game.
+    game_events.write(GameEventMessage(GameEvent::PieceSpawned(game.active.kind)));
+}
 
-    // If rotation is valid, update the active piece
-    if can_rotate {
-        active.rotation = next_rotation;
+/// Start over when [`InputAction::Restart`] is pressed mid-game.
+pub fn restart_input_system(
+    actions: Res<ActionState>,
+    mut game: ResMut<Game>,
+    mut recording: ResMut<ReplayRecording>,
+    rules: Res<GameRules>,
+    mut game_events: MessageWriter<GameEventMessage>,
+) {
+    if actions.just_pressed(InputAction::Restart) {
+        restart_game(&mut game, &mut recording, &rules, &mut game_events);
     }
-
-    // Draw the piece at its (possibly new) rotation
-    place_active_on_board(&active, &mut board);
 }
 
-fn can_place_piece(active: &ActivePiece, board: &Board) -> bool {
-    let shape = shape_of(active.kind.clone(), active.rotation);
-    for (dx, dy) in shape.cells {
-        let x = active.x + dx;
-        let y = active.y + dy;
-
-        // out of bounds => invalid
-        if x < 0 || x >= BOARD_WIDTH as i32 || y < 0 || y >= BOARD_HEIGHT as i32 {
-            return false;
-        }
-
-        // collides with existing block => invalid
-        if let Some(Cell::Filled(_)) = board.get(x, y) {
-            return false;
+/// Switch to the Game Over screen once the engine reports the game has ended.
+pub fn game_over_transition_system(
+    mut game_events: MessageReader<GameEventMessage>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for GameEventMessage(event) in game_events.read() {
+        if matches!(event, GameEvent::GameOver(_)) {
+            next_state.set(AppState::GameOver);
         }
     }
-    true
 }
 
 /// Root marker for the Game Over UI so it can be cleaned up easily.
 #[derive(Component)]
 pub struct GameOverRoot;
 
-/// Show a large "GAME OVER" sign centered on the screen.
-pub fn show_game_over_ui(mut commands: Commands) {
+/// Show a large "GAME OVER" sign centered on the screen, with the reason the game ended and
+/// the high score table (or the initials prompt, if the run made it in).
+pub fn show_game_over_ui(
+    mut commands: Commands,
+    game: Option<Res<Game>>,
+    rules: Res<GameRules>,
+    scores: Res<HighScores>,
+    entry: Option<Res<InitialsEntry>>,
+) {
+    let reason = match game.and_then(|game| game.top_out()) {
+        Some(TopOut::BlockOut) => "BLOCK OUT",
+        Some(TopOut::LockOut) => "LOCK OUT",
+        Some(TopOut::GarbageOut) => "GARBAGE OUT",
+        None => "",
+    };
     commands
         .spawn((
             Node {
@@ -417,6 +412,14 @@ pub fn show_game_over_ui(mut commands: Commands) {
                 },
                 TextColor(Color::srgb(0.9, 0.1, 0.1)),
             ));
+            parent.spawn((
+                Text::new(reason),
+                TextFont {
+                    font_size: 36.0,
+                    ..default()
+                },
+            ));
+            spawn_high_score_area(parent, entry.as_deref(), &scores, rules.mode);
 
             // Play Again Button
             parent.spawn((
@@ -461,12 +464,14 @@ pub fn cleanup_game_over_ui(mut commands: Commands, roots: Query<Entity, With<Ga
     }
 }
 
-/// Optional: from the Game Over screen, press Space to go back to the main menu.
+/// From the Game Over screen, Restart plays again and Pause goes back to the main menu.
 pub fn game_over_input_system(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
     mut next_state: ResMut<NextState<AppState>>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Space) {
+    if actions.just_pressed(InputAction::Restart) {
+        next_state.set(AppState::InGame);
+    } else if actions.just_pressed(InputAction::Pause) {
         next_state.set(AppState::MainMenu);
     }
 }
\ No newline at end of file
@@ -0,0 +1,495 @@
+use crate::game::{Game, GameMode, GameRules};
+use crate::input::{ActionState, InputAction};
+use crate::settings::{back_up_file, config_file, write_ron};
+use crate::states::AppState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HIGH_SCORES_FILE: &str = "high_scores.ron";
+/// Where a high score file that couldn't be loaded is copied before it is overwritten.
+pub const HIGH_SCORES_BACKUP: &str = "high_scores.ron.bak";
+
+/// Runs kept per mode.
+pub const TABLE_SIZE: usize = 10;
+
+const INITIALS_LEN: usize = 3;
+
+const HIGHLIGHT_COLOR: Color = Color::srgb(0.94, 0.94, 0.0);
+
+/// One finished run in a high score table.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HighScoreEntry {
+    pub initials: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// Seconds played.
+    pub time: f32,
+    /// Day the run ended, as `YYYY-MM-DD`.
+    pub date: String,
+}
+
+/// The best [`TABLE_SIZE`] runs of each mode, highest score first.
+#[derive(Resource, Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct HighScores {
+    tables: BTreeMap<GameMode, Vec<HighScoreEntry>>,
+    /// Why the high score file couldn't be used, shown on the menus until a save replaces it.
+    #[serde(skip)]
+    load_error: Option<String>,
+}
+
+impl HighScores {
+    /// Read the high score file. A missing file gives empty tables; so does an unreadable or
+    /// corrupt one, but then [`Self::load_error`] says why and the file is backed up before
+    /// the first save overwrites it.
+    pub fn load() -> Self {
+        let Some(path) = config_file(HIGH_SCORES_FILE) else {
+            return Self::default();
+        };
+        let loaded = match fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text)
+                .map_err(|error| format!("{} is corrupt: {error}", path.display())),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Self::default(),
+            Err(error) => Err(format!("Could not read {}: {error}", path.display())),
+        };
+        loaded.unwrap_or_else(|error| Self {
+            load_error: Some(error),
+            ..Self::default()
+        })
+    }
+
+    /// Write the tables to the high score file. If it couldn't be loaded, it is copied to
+    /// [`HIGH_SCORES_BACKUP`] first, and left alone if that copy fails.
+    pub fn save(&mut self) -> Result<(), String> {
+        let Some(path) = config_file(HIGH_SCORES_FILE) else {
+            return Err("No config directory to save high scores in".to_string());
+        };
+        if self.load_error.is_some() {
+            back_up_file(&path, HIGH_SCORES_BACKUP)?;
+        }
+        write_ron(&path, self)?;
+        self.load_error = None;
+        Ok(())
+    }
+
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[HighScoreEntry] {
+        self.tables.get(&mode).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether a run scoring `score` would make it into the table.
+    pub fn qualifies(&self, mode: GameMode, score: u32) -> bool {
+        let table = self.table(mode);
+        score > 0
+            && (table.len() < TABLE_SIZE || table.last().is_some_and(|last| score > last.score))
+    }
+
+    /// Add `entry` below any runs with the same score, and return its rank (0 is first) if it
+    /// stayed in the table.
+    pub fn insert(&mut self, mode: GameMode, entry: HighScoreEntry) -> Option<usize> {
+        let table = self.tables.entry(mode).or_default();
+        let rank = table
+            .iter()
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or(table.len());
+        table.insert(rank, entry);
+        table.truncate(TABLE_SIZE);
+        (rank < TABLE_SIZE).then_some(rank)
+    }
+}
+
+/// What to tell the player when the high score file couldn't be loaded because of `error`.
+pub fn high_score_load_error_message(error: &str) -> String {
+    format!(
+        "High scores could not be loaded, starting empty. The old file will be kept as \
+         {HIGH_SCORES_BACKUP} when a new score is saved.\n{error}"
+    )
+}
+
+/// A qualifying run waiting for the player's initials on the Game Over screen.
+#[derive(Resource)]
+pub struct InitialsEntry {
+    mode: GameMode,
+    entry: HighScoreEntry,
+    letters: [char; INITIALS_LEN],
+    cursor: usize,
+}
+
+impl InitialsEntry {
+    fn initials(&self) -> String {
+        self.letters.iter().collect()
+    }
+
+    fn label(&self) -> String {
+        let letters: Vec<String> = self
+            .letters
+            .iter()
+            .enumerate()
+            .map(|(i, letter)| {
+                if i == self.cursor {
+                    format!("[{letter}]")
+                } else {
+                    format!(" {letter} ")
+                }
+            })
+            .collect();
+        format!("NEW HIGH SCORE!\nEnter your initials: {}", letters.join(""))
+    }
+}
+
+/// Where the Game Over screen shows the initials prompt, then the table.
+#[derive(Component)]
+pub struct HighScoreArea;
+
+#[derive(Component)]
+pub struct InitialsText;
+
+/// Root of the high score screen opened from the main menu.
+#[derive(Component)]
+pub struct HighScoreScreenRoot;
+
+#[derive(Component)]
+pub struct HighScoresBackButton;
+
+/// Ask for initials if the run that just ended made the table.
+pub fn begin_initials_entry(
+    mut commands: Commands,
+    game: Option<Res<Game>>,
+    rules: Res<GameRules>,
+    scores: Res<HighScores>,
+) {
+    let Some(game) = game else {
+        return;
+    };
+    if !scores.qualifies(rules.mode, game.score.points) {
+        return;
+    }
+    commands.insert_resource(InitialsEntry {
+        mode: rules.mode,
+        entry: HighScoreEntry {
+            initials: String::new(),
+            score: game.score.points,
+            lines: game.lines,
+            level: game.level,
+            time: game.time(),
+            date: today(),
+        },
+        letters: ['A'; INITIALS_LEN],
+        cursor: 0,
+    });
+}
+
+/// Fill the Game Over screen's [`HighScoreArea`] with the initials prompt or the table.
+pub fn spawn_high_score_area(
+    parent: &mut ChildSpawnerCommands,
+    entry: Option<&InitialsEntry>,
+    scores: &HighScores,
+    mode: GameMode,
+) {
+    parent
+        .spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            HighScoreArea,
+        ))
+        .with_children(|area| match entry {
+            Some(entry) => {
+                area.spawn((
+                    Text::new(entry.label()),
+                    TextFont {
+                        font_size: 40.0,
+                        ..default()
+                    },
+                    TextLayout::new_with_justify(Justify::Center),
+                    InitialsText,
+                ));
+            }
+            None => spawn_table(area, scores.table(mode), None),
+        });
+}
+
+/// Type initials with the keyboard (Backspace to go back, Enter to confirm), or pick them with
+/// a gamepad's d-pad and confirm with South or Start.
+pub fn initials_input_system(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut entry: ResMut<InitialsEntry>,
+    mut scores: ResMut<HighScores>,
+    mut texts: Query<&mut Text, With<InitialsText>>,
+    areas: Query<Entity, With<HighScoreArea>>,
+) {
+    let pad = |button| gamepads.iter().any(|gamepad| gamepad.just_pressed(button));
+    let last = INITIALS_LEN - 1;
+
+    for key in keyboard_input.get_just_pressed() {
+        if let Some(letter) = key_letter(*key) {
+            let cursor = entry.cursor;
+            entry.letters[cursor] = letter;
+            entry.cursor = (cursor + 1).min(last);
+        } else if *key == KeyCode::Backspace {
+            entry.cursor = entry.cursor.saturating_sub(1);
+        }
+    }
+    if pad(GamepadButton::DPadUp) || pad(GamepadButton::DPadDown) {
+        let step = if pad(GamepadButton::DPadUp) { 1 } else { 25 };
+        let cursor = entry.cursor;
+        let letter = entry.letters[cursor] as u8 - b'A';
+        entry.letters[cursor] = (b'A' + (letter + step) % 26) as char;
+    }
+    if pad(GamepadButton::DPadRight) {
+        entry.cursor = (entry.cursor + 1).min(last);
+    }
+    if pad(GamepadButton::DPadLeft) {
+        entry.cursor = entry.cursor.saturating_sub(1);
+    }
+
+    let confirmed = keyboard_input.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter])
+        || pad(GamepadButton::South)
+        || pad(GamepadButton::Start);
+    if !confirmed {
+        if entry.is_changed() {
+            for mut text in &mut texts {
+                text.0 = entry.label();
+            }
+        }
+        return;
+    }
+
+    let rank = record(&entry, &mut scores);
+    commands.remove_resource::<InitialsEntry>();
+    for area in &areas {
+        commands
+            .entity(area)
+            .despawn_related::<Children>()
+            .with_children(|area| spawn_table(area, scores.table(entry.mode), rank));
+    }
+}
+
+/// Keep a pending run with whatever initials were entered if the player leaves without
+/// confirming.
+pub fn finish_initials_entry(
+    mut commands: Commands,
+    entry: Option<Res<InitialsEntry>>,
+    mut scores: ResMut<HighScores>,
+) {
+    if let Some(entry) = entry {
+        record(&entry, &mut scores);
+        commands.remove_resource::<InitialsEntry>();
+    }
+}
+
+fn record(entry: &InitialsEntry, scores: &mut HighScores) -> Option<usize> {
+    let rank = scores.insert(
+        entry.mode,
+        HighScoreEntry {
+            initials: entry.initials(),
+            ..entry.entry.clone()
+        },
+    );
+    if let Err(error) = scores.save() {
+        warn!("{error}");
+    }
+    rank
+}
+
+fn key_letter(key: KeyCode) -> Option<char> {
+    let name = format!("{key:?}");
+    let letter = name.strip_prefix("Key")?;
+    let mut chars = letter.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) => Some(letter),
+        _ => None,
+    }
+}
+
+/// Rank, initials, score, lines, level, time and date, one row per run.
+fn spawn_table(
+    parent: &mut ChildSpawnerCommands,
+    entries: &[HighScoreEntry],
+    highlight: Option<usize>,
+) {
+    const COLUMNS: [(&str, f32); 7] = [
+        ("#", 60.0),
+        ("Name", 100.0),
+        ("Score", 160.0),
+        ("Lines", 100.0),
+        ("Level", 100.0),
+        ("Time", 110.0),
+        ("Date", 180.0),
+    ];
+    let row = |parent: &mut ChildSpawnerCommands, cells: [String; 7], color: Color| {
+        parent.spawn(Node::default()).with_children(|row| {
+            for ((_, width), cell) in COLUMNS.iter().zip(cells) {
+                row.spawn(Node {
+                    width: Val::Px(*width),
+                    ..default()
+                })
+                .with_children(|column| {
+                    column.spawn((
+                        Text::new(cell),
+                        TextFont {
+                            font_size: 28.0,
+                            ..default()
+                        },
+                        TextColor(color),
+                    ));
+                });
+            }
+        });
+    };
+
+    if entries.is_empty() {
+        parent.spawn(Text::new("No scores yet"));
+        return;
+    }
+    row(
+        parent,
+        COLUMNS.map(|(title, _)| title.to_string()),
+        Color::WHITE,
+    );
+    for (rank, entry) in entries.iter().enumerate() {
+        let seconds = entry.time as u32;
+        let color = if highlight == Some(rank) {
+            HIGHLIGHT_COLOR
+        } else {
+            Color::WHITE
+        };
+        row(
+            parent,
+            [
+                (rank + 1).to_string(),
+                entry.initials.clone(),
+                entry.score.to_string(),
+                entry.lines.to_string(),
+                entry.level.to_string(),
+                format!("{}:{:02}", seconds / 60, seconds % 60),
+                entry.date.clone(),
+            ],
+            color,
+        );
+    }
+}
+
+pub fn spawn_high_score_screen(mut commands: Commands, scores: Res<HighScores>) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(20.0),
+                ..default()
+            },
+            HighScoreScreenRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("HIGH SCORES"),
+                TextFont {
+                    font_size: 120.0,
+                    ..default()
+                },
+            ));
+            if let Some(error) = scores.load_error() {
+                parent.spawn((
+                    Text::new(high_score_load_error_message(error)),
+                    TextFont {
+                        font_size: 24.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.9, 0.1, 0.1)),
+                    Node {
+                        max_width: Val::Px(900.0),
+                        ..default()
+                    },
+                ));
+            }
+            for mode in GameMode::ALL {
+                parent.spawn((
+                    Text::new(mode.label()),
+                    TextFont {
+                        font_size: 48.0,
+                        ..default()
+                    },
+                ));
+                spawn_table(parent, scores.table(mode), None);
+            }
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(220.0),
+                        height: Val::Px(64.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    Button,
+                    HighScoresBackButton,
+                    BackgroundColor(Color::srgb(0.2, 0.5, 0.8)),
+                ))
+                .with_children(|button| {
+                    button.spawn(Text::new("Back"));
+                });
+        });
+}
+
+pub fn cleanup_high_score_screen(
+    mut commands: Commands,
+    roots: Query<Entity, With<HighScoreScreenRoot>>,
+) {
+    for entity in &roots {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Back to the main menu with the Back button or [`InputAction::Pause`].
+pub fn high_score_back_system(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<HighScoresBackButton>)>,
+    actions: Res<ActionState>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let pressed = buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed);
+    if pressed || actions.just_pressed(InputAction::Pause) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date in the proleptic Gregorian
+/// calendar (Howard Hinnant's `civil_from_days`).
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,253 @@
+use crate::game::{Game, GameEventMessage};
+use crate::palette::Palette;
+use bevy::prelude::*;
+use tetris::engine::GameEvent;
+use tetris::randomizer::LOOKAHEAD;
+use tetris::scoring::TSpin;
+use tetris::tetrominoes::{TetrominoKind, shape_of};
+
+/// Width of the HUD column to the right of the board.
+pub const HUD_WIDTH: f32 = 220.0;
+
+/// Gap between the HUD column and the right edge of the window.
+pub const HUD_MARGIN: f32 = 20.0;
+
+/// Side length of one block in the HUD piece previews.
+const PREVIEW_CELL: f32 = 24.0;
+
+/// Colour of the held piece while hold is used up until the next lock.
+const HOLD_UNAVAILABLE_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
+
+/// Seconds an announcement such as "T-SPIN DOUBLE" stays on screen.
+const ANNOUNCEMENT_SECONDS: f32 = 2.0;
+
+/// How many upcoming pieces the HUD shows under "Next:" (1 to [`LOOKAHEAD`]).
+#[derive(Resource)]
+pub struct PreviewConfig {
+    pub next_count: usize,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self { next_count: 5 }
+    }
+}
+
+/// The "Score:" label, kept in sync with the game's points.
+#[derive(Component)]
+pub struct ScoreText;
+
+/// The "Level:" label, kept in sync with the game's level.
+#[derive(Component)]
+pub struct LevelText;
+
+/// Short-lived callouts for special clears, under the hold slot.
+#[derive(Component)]
+pub struct AnnouncementText;
+
+/// One block of the mini piece shown in next-queue slot `slot`.
+#[derive(Component)]
+pub struct NextPieceCell {
+    slot: usize,
+    block: usize,
+}
+
+/// One block of the mini piece shown in the hold slot.
+#[derive(Component)]
+pub struct HoldPieceCell {
+    block: usize,
+}
+
+/// Spawn the empty next-queue slots under the "Next:" label; [`update_next_queue_system`]
+/// fills them in.
+pub fn spawn_next_queue(parent: &mut ChildSpawnerCommands, config: &PreviewConfig) {
+    parent
+        .spawn(Node {
+            width: Val::Px(HUD_WIDTH),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(PREVIEW_CELL / 2.0),
+            ..default()
+        })
+        .with_children(|queue| {
+            for slot in 0..config.next_count.clamp(1, LOOKAHEAD) {
+                spawn_piece_slot(queue, |block| NextPieceCell { slot, block });
+            }
+        });
+}
+
+/// Spawn the (initially empty) hold slot; [`update_hold_slot_system`] fills it in.
+pub fn spawn_hold_slot(parent: &mut ChildSpawnerCommands) {
+    parent
+        .spawn(Node {
+            width: Val::Px(HUD_WIDTH),
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_children(|hold| {
+            spawn_piece_slot(hold, |block| HoldPieceCell { block });
+        });
+}
+
+/// A 4x2 preview slot holding four absolutely positioned blocks tagged by `marker`.
+fn spawn_piece_slot<B: Bundle>(parent: &mut ChildSpawnerCommands, marker: impl Fn(usize) -> B) {
+    parent
+        .spawn(Node {
+            width: Val::Px(4.0 * PREVIEW_CELL),
+            height: Val::Px(2.0 * PREVIEW_CELL),
+            ..default()
+        })
+        .with_children(|piece| {
+            for block in 0..4 {
+                piece.spawn((
+                    Node {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(PREVIEW_CELL - 1.0),
+                        height: Val::Px(PREVIEW_CELL - 1.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::NONE),
+                    marker(block),
+                ));
+            }
+        });
+}
+
+/// Redraw the next queue when it is first shown and whenever a new piece spawns.
+pub fn update_next_queue_system(
+    game: Res<Game>,
+    palette: Res<Palette>,
+    mut game_events: MessageReader<GameEventMessage>,
+    added: Query<(), Added<NextPieceCell>>,
+    mut cells: Query<(&NextPieceCell, &mut Node, &mut BackgroundColor)>,
+) {
+    let spawned = game_events
+        .read()
+        .any(|GameEventMessage(event)| matches!(event, GameEvent::PieceSpawned(_)));
+    if !spawned && added.is_empty() && !palette.is_changed() {
+        return;
+    }
+
+    let upcoming: Vec<TetrominoKind> = game.randomizer.peek().collect();
+    for (cell, mut node, mut background) in &mut cells {
+        let Some(&kind) = upcoming.get(cell.slot) else {
+            continue;
+        };
+        let (left, top) = preview_offset(kind, cell.block);
+        node.left = Val::Px(left);
+        node.top = Val::Px(top);
+        background.0 = palette.piece(kind);
+    }
+}
+
+/// Redraw the hold slot when it is first shown, on a hold, on the lock that re-enables it and
+/// when a new game starts.
+pub fn update_hold_slot_system(
+    game: Res<Game>,
+    palette: Res<Palette>,
+    mut game_events: MessageReader<GameEventMessage>,
+    added: Query<(), Added<HoldPieceCell>>,
+    mut cells: Query<(&HoldPieceCell, &mut Node, &mut BackgroundColor)>,
+) {
+    let changed = game_events.read().any(|GameEventMessage(event)| {
+        matches!(
+            event,
+            GameEvent::PieceHeld(_) | GameEvent::PieceLocked | GameEvent::PieceSpawned(_)
+        )
+    });
+    if !changed && added.is_empty() && !palette.is_changed() {
+        return;
+    }
+
+    for (cell, mut node, mut background) in &mut cells {
+        let Some(kind) = game.held else {
+            node.display = Display::None;
+            continue;
+        };
+        let (left, top) = preview_offset(kind, cell.block);
+        node.display = Display::Flex;
+        node.left = Val::Px(left);
+        node.top = Val::Px(top);
+        background.0 = if game.can_hold() {
+            palette.piece(kind)
+        } else {
+            HOLD_UNAVAILABLE_COLOR
+        };
+    }
+}
+
+pub fn update_score_text_system(game: Res<Game>, mut texts: Query<&mut Text, With<ScoreText>>) {
+    let label = format!("Score: {}", game.score.points);
+    for mut text in &mut texts {
+        if text.0 != label {
+            text.0 = label.clone();
+        }
+    }
+}
+
+pub fn update_level_text_system(game: Res<Game>, mut texts: Query<&mut Text, With<LevelText>>) {
+    let label = format!("Level: {}", game.level);
+    for mut text in &mut texts {
+        if text.0 != label {
+            text.0 = label.clone();
+        }
+    }
+}
+
+/// Call out T-spins reported by the engine, then clear the text after a short while.
+pub fn announce_t_spin_system(
+    time: Res<Time>,
+    mut game_events: MessageReader<GameEventMessage>,
+    mut texts: Query<&mut Text, With<AnnouncementText>>,
+    mut remaining: Local<f32>,
+) {
+    for GameEventMessage(event) in game_events.read() {
+        if let GameEvent::TSpin { spin, lines } = *event {
+            let label = t_spin_label(spin, lines);
+            for mut text in &mut texts {
+                text.0 = label.clone();
+            }
+            *remaining = ANNOUNCEMENT_SECONDS;
+        }
+    }
+
+    if *remaining > 0.0 {
+        *remaining -= time.delta_secs();
+        if *remaining <= 0.0 {
+            for mut text in &mut texts {
+                text.0.clear();
+            }
+        }
+    }
+}
+
+fn t_spin_label(spin: TSpin, lines: u32) -> String {
+    let spin = match spin {
+        TSpin::Mini => "T-SPIN MINI",
+        _ => "T-SPIN",
+    };
+    match lines {
+        0 => spin.to_string(),
+        1 => format!("{spin} SINGLE"),
+        2 => format!("{spin} DOUBLE"),
+        _ => format!("{spin} TRIPLE"),
+    }
+}
+
+/// Pixel offset of `block` of `kind`'s spawn state, centred in a 4x2 preview slot.
+fn preview_offset(kind: TetrominoKind, block: usize) -> (f32, f32) {
+    let cells = shape_of(kind, 0).cells;
+    let min_x = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let max_x = cells.iter().map(|&(x, _)| x).max().unwrap_or(0);
+    let max_y = cells.iter().map(|&(_, y)| y).max().unwrap_or(0);
+    let min_y = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+
+    let width = (max_x - min_x + 1) as f32;
+    let height = (max_y - min_y + 1) as f32;
+    let (x, y) = cells[block];
+    (
+        (x - min_x) as f32 * PREVIEW_CELL + (4.0 - width) * PREVIEW_CELL / 2.0,
+        // rows grow upwards on the board but downwards in the UI
+        (max_y - y) as f32 * PREVIEW_CELL + (2.0 - height) * PREVIEW_CELL / 2.0,
+    )
+}
@@ -0,0 +1,398 @@
+use bevy::input::gamepad::{GamepadConnection, GamepadConnectionEvent};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tetris::engine::{Action, Inputs};
+
+/// Everything a key can be bound to: the engine's [`Action`]s plus the app-level controls.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputAction {
+    MoveLeft,
+    MoveRight,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    SoftDrop,
+    HardDrop,
+    Hold,
+    Pause,
+    Restart,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 10] = [
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::RotateCw,
+        InputAction::RotateCcw,
+        InputAction::Rotate180,
+        InputAction::SoftDrop,
+        InputAction::HardDrop,
+        InputAction::Hold,
+        InputAction::Pause,
+        InputAction::Restart,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            InputAction::MoveLeft => "Move Left",
+            InputAction::MoveRight => "Move Right",
+            InputAction::RotateCw => "Rotate CW",
+            InputAction::RotateCcw => "Rotate CCW",
+            InputAction::Rotate180 => "Rotate 180",
+            InputAction::SoftDrop => "Soft Drop",
+            InputAction::HardDrop => "Hard Drop",
+            InputAction::Hold => "Hold",
+            InputAction::Pause => "Pause",
+            InputAction::Restart => "Restart",
+        }
+    }
+
+    /// The engine action this drives, if it is part of gameplay.
+    pub fn game_action(self) -> Option<Action> {
+        match self {
+            InputAction::MoveLeft => Some(Action::MoveLeft),
+            InputAction::MoveRight => Some(Action::MoveRight),
+            InputAction::RotateCw => Some(Action::RotateCw),
+            InputAction::RotateCcw => Some(Action::RotateCcw),
+            InputAction::Rotate180 => Some(Action::Rotate180),
+            InputAction::SoftDrop => Some(Action::SoftDrop),
+            InputAction::HardDrop => Some(Action::HardDrop),
+            InputAction::Hold => Some(Action::Hold),
+            InputAction::Pause | InputAction::Restart => None,
+        }
+    }
+}
+
+/// A key or gamepad button that can trigger an [`InputAction`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButton),
+}
+
+impl Binding {
+    /// A short readable name, e.g. "A" rather than "KeyA".
+    pub fn name(self) -> String {
+        let name = match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::Button(button) => format!("Pad {button:?}"),
+        };
+        for prefix in ["Key", "Digit", "Arrow"] {
+            if let Some(rest) = name.strip_prefix(prefix)
+                && !rest.is_empty()
+            {
+                return rest.to_string();
+            }
+        }
+        name
+    }
+}
+
+/// Which keys and gamepad buttons trigger each [`InputAction`]. An action may have any number
+/// of bindings, but a key or button drives at most one action.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: Vec<(InputAction, Vec<Binding>)>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::{Button, Key};
+        Self {
+            bindings: vec![
+                (
+                    InputAction::MoveLeft,
+                    vec![
+                        Key(KeyCode::KeyA),
+                        Key(KeyCode::ArrowLeft),
+                        Button(GamepadButton::DPadLeft),
+                    ],
+                ),
+                (
+                    InputAction::MoveRight,
+                    vec![
+                        Key(KeyCode::KeyD),
+                        Key(KeyCode::ArrowRight),
+                        Button(GamepadButton::DPadRight),
+                    ],
+                ),
+                (
+                    InputAction::RotateCw,
+                    vec![
+                        Key(KeyCode::KeyW),
+                        Key(KeyCode::ArrowUp),
+                        Button(GamepadButton::East),
+                    ],
+                ),
+                (
+                    InputAction::RotateCcw,
+                    vec![Key(KeyCode::KeyQ), Button(GamepadButton::South)],
+                ),
+                (
+                    InputAction::Rotate180,
+                    vec![Key(KeyCode::KeyE), Button(GamepadButton::North)],
+                ),
+                (
+                    InputAction::SoftDrop,
+                    vec![
+                        Key(KeyCode::KeyS),
+                        Key(KeyCode::ArrowDown),
+                        Button(GamepadButton::DPadDown),
+                    ],
+                ),
+                (
+                    InputAction::HardDrop,
+                    vec![Key(KeyCode::Space), Button(GamepadButton::DPadUp)],
+                ),
+                (
+                    InputAction::Hold,
+                    vec![
+                        Key(KeyCode::KeyC),
+                        Key(KeyCode::ShiftLeft),
+                        Button(GamepadButton::West),
+                        Button(GamepadButton::LeftTrigger),
+                        Button(GamepadButton::RightTrigger),
+                    ],
+                ),
+                (
+                    InputAction::Pause,
+                    vec![
+                        Key(KeyCode::Escape),
+                        Key(KeyCode::KeyP),
+                        Button(GamepadButton::Start),
+                    ],
+                ),
+                (
+                    InputAction::Restart,
+                    vec![Key(KeyCode::KeyR), Button(GamepadButton::Select)],
+                ),
+            ],
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: InputAction) -> &[Binding] {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map_or(&[], |(_, bindings)| bindings.as_slice())
+    }
+
+    /// Bind `binding` to `action`, taking it away from whichever action had it before.
+    pub fn bind(&mut self, action: InputAction, binding: Binding) {
+        for (_, bindings) in &mut self.bindings {
+            bindings.retain(|bound| *bound != binding);
+        }
+        match self.bindings.iter_mut().find(|(bound, _)| *bound == action) {
+            Some((_, bindings)) => bindings.push(binding),
+            None => self.bindings.push((action, vec![binding])),
+        }
+    }
+
+    pub fn unbind(&mut self, action: InputAction, binding: Binding) {
+        if let Some((_, bindings)) = self.bindings.iter_mut().find(|(bound, _)| *bound == action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    pub fn clear(&mut self, action: InputAction) {
+        if let Some((_, bindings)) = self.bindings.iter_mut().find(|(bound, _)| *bound == action) {
+            bindings.clear();
+        }
+    }
+
+    /// Give actions with no entry their default bindings, and drop any binding claimed by an
+    /// earlier action so each key or button drives only one.
+    pub fn repair(&mut self) {
+        let defaults = Self::default();
+        for action in InputAction::ALL {
+            if !self.bindings.iter().any(|(bound, _)| *bound == action) {
+                self.bindings
+                    .push((action, defaults.bindings(action).to_vec()));
+            }
+        }
+        let mut seen = Vec::new();
+        for (_, bindings) in &mut self.bindings {
+            bindings.retain(|binding| {
+                let first = !seen.contains(binding);
+                seen.push(*binding);
+                first
+            });
+        }
+    }
+
+    /// "A, Left, Pad DPadLeft" style list of what is bound to `action`.
+    pub fn describe(&self, action: InputAction) -> String {
+        let bindings = self.bindings(action);
+        if bindings.is_empty() {
+            return "-".to_string();
+        }
+        bindings
+            .iter()
+            .map(|binding| binding.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The gamepad each player uses, in the order they were plugged in. A slot is freed when its
+/// gamepad disconnects and reused by the next one to connect, so other players keep theirs.
+#[derive(Resource, Default, Debug)]
+pub struct Players {
+    gamepads: Vec<Option<Entity>>,
+}
+
+impl Players {
+    /// The gamepad of `player` (0 is player 1), if one is connected.
+    pub fn gamepad(&self, player: usize) -> Option<Entity> {
+        self.gamepads.get(player).copied().flatten()
+    }
+
+    pub fn player_of(&self, gamepad: Entity) -> Option<usize> {
+        self.gamepads.iter().position(|&slot| slot == Some(gamepad))
+    }
+
+    fn assign(&mut self, gamepad: Entity) -> usize {
+        if let Some(player) = self.player_of(gamepad) {
+            return player;
+        }
+        match self.gamepads.iter().position(Option::is_none) {
+            Some(player) => {
+                self.gamepads[player] = Some(gamepad);
+                player
+            }
+            None => {
+                self.gamepads.push(Some(gamepad));
+                self.gamepads.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, gamepad: Entity) -> Option<usize> {
+        let player = self.player_of(gamepad)?;
+        self.gamepads[player] = None;
+        Some(player)
+    }
+}
+
+/// Give each gamepad a player slot as it is plugged in, and free the slot when it is unplugged.
+pub fn assign_gamepads_system(
+    mut connections: MessageReader<GamepadConnectionEvent>,
+    mut players: ResMut<Players>,
+) {
+    for event in connections.read() {
+        match &event.connection {
+            GamepadConnection::Connected { name, .. } => {
+                let player = players.assign(event.gamepad);
+                info!("gamepad \"{name}\" connected as player {}", player + 1);
+            }
+            GamepadConnection::Disconnected => {
+                if let Some(player) = players.release(event.gamepad) {
+                    info!("gamepad of player {} disconnected", player + 1);
+                }
+            }
+        }
+    }
+}
+
+/// Which [`InputAction`]s are down this frame, from the keyboard and player 1's gamepad.
+/// Systems read this instead of raw devices so every binding works everywhere.
+#[derive(Resource, Default, Debug)]
+pub struct ActionState {
+    just_pressed: Vec<InputAction>,
+    pressed: Vec<InputAction>,
+}
+
+impl ActionState {
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    /// The gameplay part of this frame's input, queued for the next tick by [`InputQueue`].
+    pub fn inputs(&self) -> Inputs {
+        Inputs {
+            pressed: self
+                .just_pressed
+                .iter()
+                .filter_map(|a| a.game_action())
+                .collect(),
+            held: self
+                .pressed
+                .iter()
+                .filter_map(|a| a.game_action())
+                .collect(),
+        }
+    }
+}
+
+/// Resolve this frame's device state into [`ActionState`] through the [`InputMap`].
+pub fn update_action_state_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    players: Res<Players>,
+    input_map: Res<InputMap>,
+    mut state: ResMut<ActionState>,
+) {
+    let gamepad = players
+        .gamepad(0)
+        .and_then(|entity| gamepads.get(entity).ok());
+    let just_pressed = |binding: &Binding| match *binding {
+        Binding::Key(key) => keyboard_input.just_pressed(key),
+        Binding::Button(button) => gamepad.is_some_and(|gamepad| gamepad.just_pressed(button)),
+    };
+    let pressed = |binding: &Binding| match *binding {
+        Binding::Key(key) => keyboard_input.pressed(key),
+        Binding::Button(button) => gamepad.is_some_and(|gamepad| gamepad.pressed(button)),
+    };
+
+    state.just_pressed.clear();
+    state.pressed.clear();
+    for action in InputAction::ALL {
+        let bindings = input_map.bindings(action);
+        if bindings.iter().any(just_pressed) {
+            state.just_pressed.push(action);
+        }
+        if bindings.iter().any(pressed) {
+            state.pressed.push(action);
+        }
+    }
+}
+
+/// Gameplay input gathered frame by frame until the next simulation tick takes it, so every
+/// press lands on exactly one tick however many ticks a frame runs, including none.
+#[derive(Resource, Default, Debug)]
+pub struct InputQueue {
+    pressed: Vec<Action>,
+    held: Vec<Action>,
+}
+
+impl InputQueue {
+    pub fn clear(&mut self) {
+        self.pressed.clear();
+        self.held.clear();
+    }
+
+    /// Input for the next tick: everything pressed since the last one, and what is held now.
+    pub fn take(&mut self) -> Inputs {
+        Inputs {
+            pressed: std::mem::take(&mut self.pressed),
+            held: self.held.clone(),
+        }
+    }
+}
+
+/// Add this frame's gameplay input from [`ActionState`] to the [`InputQueue`].
+pub fn queue_inputs_system(actions: Res<ActionState>, mut queue: ResMut<InputQueue>) {
+    let inputs = actions.inputs();
+    for action in inputs.pressed {
+        if !queue.pressed.contains(&action) {
+            queue.pressed.push(action);
+        }
+    }
+    queue.held = inputs.held;
+}
+
+/// The action waiting for a key on the rebinding screen, if any.
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<InputAction>);
@@ -0,0 +1,9 @@
+//! Game rules for Tet.rs: the board, the pieces and the tick-driven [`engine::GameState`].
+//! Nothing in here touches the ECS, windowing or input devices, so it runs headless.
+
+pub mod board;
+pub mod engine;
+pub mod randomizer;
+pub mod replay;
+pub mod scoring;
+pub mod tetrominoes;
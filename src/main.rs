@@ -1,14 +1,21 @@
-mod board;
 mod game;
+mod highscores;
+mod hud;
+mod input;
 mod menu;
+mod palette;
+mod pause;
+mod replays;
+mod settings;
 mod states;
-mod tetrominoes;
 
+use bevy::input::InputSystems;
 use bevy::prelude::*;
+use bevy::ui::UiSystems;
 use bevy::window::{Window, WindowPlugin, WindowResolution};
-use board::{BOARD_HEIGHT, BOARD_WIDTH, Board, Cell};
 use menu::{cleanup_menu, menu_button_system, spawn_menu};
-use states::AppState;
+use states::{AppState, PauseState, board_active};
+use tetris::engine::TICK_RATE;
 
 //todo: add tetris music
 //todo: get assets for the bricks
@@ -34,6 +41,11 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 }
 
 fn main() {
+    let (settings, load_error) = match settings::Settings::load() {
+        Ok(settings) => (settings, None),
+        Err(error) => (settings::Settings::default(), Some(error)),
+    };
+
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -41,34 +53,128 @@ fn main() {
                 // use integers (u32) and chain the builder method:
                 resolution: WindowResolution::new(1200, 1800).with_scale_factor_override(1.0),
                 resizable: false,
+                mode: settings.window_mode.into(),
                 ..default()
             }),
             ..default()
         }))
         .init_state::<AppState>()
+        .add_sub_state::<PauseState>()
+        .add_message::<game::GameEventMessage>()
+        .init_resource::<hud::PreviewConfig>()
+        .init_resource::<game::GameRules>()
+        .init_resource::<game::GhostConfig>()
+        .init_resource::<input::InputMap>()
+        .init_resource::<input::Rebinding>()
+        .init_resource::<input::Players>()
+        .init_resource::<input::ActionState>()
+        .init_resource::<input::InputQueue>()
+        .insert_resource(Time::<Fixed>::from_hz(TICK_RATE as f64))
+        .init_resource::<menu::MenuFocus>()
+        .init_resource::<palette::Palette>()
+        .insert_resource(settings)
+        .insert_resource(highscores::HighScores::load())
+        .insert_resource(settings::SettingsLoadError(load_error))
         .add_systems(Startup, setup)
-        .add_systems(OnEnter(AppState::MainMenu), spawn_menu)
+        .add_systems(
+            PreUpdate,
+            (
+                (
+                    input::assign_gamepads_system,
+                    input::update_action_state_system,
+                    input::queue_inputs_system.run_if(in_state(PauseState::Running)),
+                )
+                    .chain()
+                    .after(InputSystems),
+                menu::gamepad_menu_navigation_system.after(UiSystems::Focus),
+            ),
+        )
+        .add_systems(OnEnter(AppState::MainMenu), (game::cleanup_ingame, spawn_menu))
         .add_systems(OnExit(AppState::MainMenu), cleanup_menu)
         .add_systems(Update, menu_button_system)
+        .add_systems(
+            FixedUpdate,
+            game::game_tick_system.run_if(in_state(PauseState::Running)),
+        )
         .add_systems(
             OnEnter(AppState::InGame),
-            (game::setup_ingame, game::setup_fall_timer, game::spawn_first_piece, game::reset_board),
+            (game::cleanup_ingame, game::start_game, game::setup_ingame).chain(),
+        )
+        .add_systems(OnExit(AppState::InGame), replays::save_replay_system)
+        .add_systems(OnEnter(PauseState::Running), pause::show_board)
+        .add_systems(
+            OnEnter(PauseState::Paused),
+            (pause::clear_input_queue, pause::hide_board, pause::spawn_pause_menu),
+        )
+        .add_systems(OnExit(PauseState::Paused), pause::cleanup_pause_menu)
+        .add_systems(OnEnter(PauseState::Settings), pause::spawn_settings_menu)
+        .add_systems(OnExit(PauseState::Settings), pause::cleanup_pause_menu)
+        .add_systems(OnEnter(PauseState::Controls), pause::spawn_controls_menu)
+        .add_systems(OnExit(PauseState::Controls), pause::cleanup_pause_menu)
+        .add_systems(
+            OnEnter(AppState::GameOver),
+            (highscores::begin_initials_entry, game::show_game_over_ui).chain(),
         )
-        .add_systems(OnEnter(AppState::GameOver), game::show_game_over_ui)
-        .add_systems(OnExit(AppState::GameOver), game::cleanup_game_over_ui)
-        .insert_resource(Board {
-            cells: [[Cell::Empty; BOARD_WIDTH]; BOARD_HEIGHT],
-        })
+        .add_systems(
+            OnExit(AppState::GameOver),
+            (game::cleanup_game_over_ui, highscores::finish_initials_entry),
+        )
+        .add_systems(OnEnter(AppState::HighScores), highscores::spawn_high_score_screen)
+        .add_systems(OnExit(AppState::HighScores), highscores::cleanup_high_score_screen)
+        .add_systems(OnEnter(AppState::Replays), replays::spawn_replay_browser)
+        .add_systems(OnExit(AppState::Replays), replays::cleanup_replay_browser)
+        .add_systems(
+            OnEnter(AppState::ReplayViewer),
+            (
+                game::cleanup_ingame,
+                replays::start_replay,
+                game::setup_ingame,
+                replays::spawn_replay_controls,
+            )
+                .chain(),
+        )
+        .add_systems(OnExit(AppState::ReplayViewer), game::cleanup_ingame)
         .add_systems(
             Update,
             (
                 menu_button_system.run_if(in_state(AppState::MainMenu).or(in_state(AppState::GameOver))),
-                game::sync_board.run_if(in_state(AppState::InGame)),
-                game::fall_piece_system.run_if(in_state(AppState::InGame)),
-                game::move_piece_horizontal_system.run_if(in_state(AppState::InGame)),
-                game::rotate_piece_system.run_if(in_state(AppState::InGame)),
-                game::hard_drop_system.run_if(in_state(AppState::InGame)),
-                game::game_over_input_system.run_if(in_state(AppState::GameOver)),
+                (game::resize_board_system, game::sync_board)
+                    .chain()
+                    .run_if(board_active),
+                game::restart_input_system.run_if(in_state(PauseState::Running)),
+                game::game_over_transition_system.run_if(in_state(AppState::InGame)),
+                hud::update_next_queue_system.run_if(board_active),
+                hud::update_hold_slot_system.run_if(board_active),
+                hud::update_score_text_system.run_if(in_state(AppState::InGame).or(in_state(AppState::ReplayViewer))),
+                hud::update_level_text_system.run_if(in_state(AppState::InGame).or(in_state(AppState::ReplayViewer))),
+                hud::announce_t_spin_system.run_if(board_active),
+                pause::pause_input_system.run_if(in_state(AppState::InGame)),
+                pause::auto_pause_system.run_if(in_state(PauseState::Running)),
+                pause::pause_button_system.run_if(in_state(AppState::InGame)),
+                pause::update_button_labels_system.run_if(in_state(AppState::InGame)),
+                pause::rebind_system.run_if(in_state(PauseState::Controls)),
+                game::game_over_input_system.run_if(
+                    in_state(AppState::GameOver)
+                        .and(not(resource_exists::<highscores::InitialsEntry>)),
+                ),
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                settings::apply_settings_system.run_if(resource_changed::<settings::Settings>),
+                settings::save_settings_system,
+                highscores::initials_input_system
+                    .run_if(resource_exists::<highscores::InitialsEntry>),
+                highscores::high_score_back_system.run_if(in_state(AppState::HighScores)),
+                replays::replay_browser_system.run_if(in_state(AppState::Replays)),
+                (
+                    replays::replay_controls_system,
+                    replays::replay_playback_system,
+                    replays::update_replay_status_system,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::ReplayViewer)),
             ),
         )
         .run();
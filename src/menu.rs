@@ -1,4 +1,7 @@
 use crate::MenuBackground;
+use crate::highscores::{HighScores, InitialsEntry, high_score_load_error_message};
+use crate::input::Rebinding;
+use crate::settings::{SETTINGS_BACKUP, SettingsLoadError};
 use crate::states::AppState;
 use bevy::app::AppExit;
 use bevy::prelude::*;
@@ -12,7 +15,23 @@ pub struct StartButton;
 #[derive(Component)]
 pub struct QuitButton;
 
-pub fn spawn_menu(mut commands: Commands) {
+#[derive(Component)]
+pub struct HighScoresButton;
+
+#[derive(Component)]
+pub struct ReplaysButton;
+
+/// The button a gamepad has moved to with the d-pad, outlined so players can see it.
+#[derive(Resource, Default)]
+pub struct MenuFocus(Option<Entity>);
+
+const FOCUS_OUTLINE: Color = Color::WHITE;
+
+pub fn spawn_menu(
+    mut commands: Commands,
+    load_error: Res<SettingsLoadError>,
+    scores: Res<HighScores>,
+) {
     commands
         .spawn((
             Node {
@@ -48,6 +67,36 @@ pub fn spawn_menu(mut commands: Commands) {
                     .with_children(|button| {
                         button.spawn(Text::new("Start"));
                     });
+                    col.spawn((
+                        Node {
+                            width: Val::Px(220.0),
+                            height: Val::Px(64.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        Button,
+                        HighScoresButton,
+                        BackgroundColor(Color::srgb(0.2, 0.5, 0.8)),
+                    ))
+                    .with_children(|button| {
+                        button.spawn(Text::new("High Scores"));
+                    });
+                    col.spawn((
+                        Node {
+                            width: Val::Px(220.0),
+                            height: Val::Px(64.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        Button,
+                        ReplaysButton,
+                        BackgroundColor(Color::srgb(0.2, 0.5, 0.8)),
+                    ))
+                    .with_children(|button| {
+                        button.spawn(Text::new("Replays"));
+                    });
                     col.spawn((
                         Node {
                             width: Val::Px(220.0),
@@ -63,16 +112,48 @@ pub fn spawn_menu(mut commands: Commands) {
                     .with_children(|button| {
                         button.spawn(Text::new("Quit"));
                     });
+                    if let Some(error) = &load_error.0 {
+                        spawn_load_error(
+                            col,
+                            format!(
+                                "Settings could not be loaded, using defaults. The old file \
+                                 will be kept as {SETTINGS_BACKUP} when a setting is \
+                                 changed.\n{error}"
+                            ),
+                        );
+                    }
+                    if let Some(error) = scores.load_error() {
+                        spawn_load_error(col, high_score_load_error_message(error));
+                    }
                 });
         });
 }
 
+/// A red note on the main menu explaining why a saved file was ignored.
+fn spawn_load_error(parent: &mut ChildSpawnerCommands, message: String) {
+    parent.spawn((
+        Text::new(message),
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.9, 0.1, 0.1)),
+        Node {
+            max_width: Val::Px(900.0),
+            ..default()
+        },
+    ));
+}
+
+#[allow(clippy::type_complexity)]
 pub fn menu_button_system(
     mut changed: Query<
         (
             Entity,
             &Interaction,
             Option<&StartButton>,
+            Option<&HighScoresButton>,
+            Option<&ReplaysButton>,
             Option<&QuitButton>,
         ),
         (Changed<Interaction>, With<Button>),
@@ -80,10 +161,14 @@ pub fn menu_button_system(
     mut next_state: ResMut<NextState<AppState>>,
     mut app_exit: MessageWriter<AppExit>,
 ) {
-    for (_entity, interaction, is_start, is_quit) in changed.iter_mut() {
+    for (_entity, interaction, is_start, is_high_scores, is_replays, is_quit) in changed.iter_mut() {
         if *interaction == Interaction::Pressed {
             if is_start.is_some() {
                 next_state.set(AppState::InGame);
+            } else if is_high_scores.is_some() {
+                next_state.set(AppState::HighScores);
+            } else if is_replays.is_some() {
+                next_state.set(AppState::Replays);
             } else if is_quit.is_some() {
                 app_exit.write(AppExit::Success);
             }
@@ -103,3 +188,81 @@ pub fn cleanup_menu(
         commands.entity(entity).despawn();
     }
 }
+
+/// Move [`MenuFocus`] between the visible buttons with any gamepad's d-pad and press the focused
+/// one with South, so every menu works without a mouse. Runs right after UI focus, so button
+/// systems see the press like a click.
+pub fn gamepad_menu_navigation_system(
+    mut commands: Commands,
+    gamepads: Query<&Gamepad>,
+    rebinding: Res<Rebinding>,
+    initials_entry: Option<Res<InitialsEntry>>,
+    mut focus: ResMut<MenuFocus>,
+    mut buttons: Query<
+        (
+            Entity,
+            &UiGlobalTransform,
+            &InheritedVisibility,
+            &mut Interaction,
+        ),
+        With<Button>,
+    >,
+    mut pressed: Local<Option<Entity>>,
+) {
+    // A press only lasts a frame; nothing else releases it without a mouse.
+    if let Some(entity) = pressed.take()
+        && let Ok((.., mut interaction)) = buttons.get_mut(entity)
+    {
+        *interaction = Interaction::None;
+    }
+    // The rebinding screen and the initials prompt listen for buttons themselves.
+    if rebinding.0.is_some() || initials_entry.is_some() {
+        return;
+    }
+
+    let mut visible: Vec<(Entity, Vec2)> = buttons
+        .iter()
+        .filter(|(_, _, visibility, _)| visibility.get())
+        .map(|(entity, transform, _, _)| (entity, transform.translation))
+        .collect();
+    visible.sort_by(|(_, a), (_, b)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+
+    let step = |button| gamepads.iter().any(|gamepad| gamepad.just_pressed(button));
+    let current = focus
+        .0
+        .and_then(|entity| visible.iter().position(|&(e, _)| e == entity));
+    let next = match (
+        current,
+        step(GamepadButton::DPadUp),
+        step(GamepadButton::DPadDown),
+    ) {
+        (_, _, _) if visible.is_empty() => None,
+        (None, true, _) | (None, _, true) => Some(0),
+        (Some(i), true, false) => Some(i.checked_sub(1).unwrap_or(visible.len() - 1)),
+        (Some(i), false, true) => Some((i + 1) % visible.len()),
+        (current, _, _) => current,
+    };
+    let next = next.map(|i| visible[i].0);
+
+    if next != focus.0 {
+        if let Some(previous) = focus.0
+            && let Ok(mut entity) = commands.get_entity(previous)
+        {
+            entity.remove::<Outline>();
+        }
+        if let Some(entity) = next {
+            commands
+                .entity(entity)
+                .insert(Outline::new(Val::Px(4.0), Val::Px(2.0), FOCUS_OUTLINE));
+        }
+        focus.0 = next;
+    }
+
+    if let Some(entity) = focus.0
+        && step(GamepadButton::South)
+        && let Ok((.., mut interaction)) = buttons.get_mut(entity)
+    {
+        *interaction = Interaction::Pressed;
+        *pressed = Some(entity);
+    }
+}
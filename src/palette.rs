@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+use tetris::board::Cell;
+use tetris::tetrominoes::TetrominoKind;
+
+/// Colour of an empty board square.
+const EMPTY_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.7);
+
+/// Maps what is on the board to colours. Swap the resource to reskin the game.
+#[derive(Resource, Clone, Debug)]
+pub struct Palette {
+    /// Indexed in [`TetrominoKind::ALL`] order.
+    pub pieces: [Color; 7],
+    pub garbage: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::guideline()
+    }
+}
+
+impl Palette {
+    /// The standard guideline colours.
+    pub fn guideline() -> Self {
+        Self {
+            pieces: [
+                Color::srgb(0.0, 0.94, 0.94), // I cyan
+                Color::srgb(0.94, 0.94, 0.0), // O yellow
+                Color::srgb(0.63, 0.0, 0.94), // T purple
+                Color::srgb(0.0, 0.94, 0.0),  // S green
+                Color::srgb(0.94, 0.0, 0.0),  // Z red
+                Color::srgb(0.0, 0.0, 0.94),  // J blue
+                Color::srgb(0.94, 0.63, 0.0), // L orange
+            ],
+            garbage: Color::srgb(0.5, 0.5, 0.5),
+        }
+    }
+
+    /// Okabe-Ito colours, told apart with the common forms of colour blindness.
+    pub fn color_blind() -> Self {
+        Self {
+            pieces: [
+                Color::srgb(0.34, 0.71, 0.91), // I sky blue
+                Color::srgb(0.94, 0.89, 0.26), // O yellow
+                Color::srgb(0.8, 0.47, 0.65),  // T reddish purple
+                Color::srgb(0.0, 0.62, 0.45),  // S bluish green
+                Color::srgb(0.84, 0.37, 0.0),  // Z vermillion
+                Color::srgb(0.0, 0.45, 0.7),   // J blue
+                Color::srgb(0.9, 0.62, 0.0),   // L orange
+            ],
+            garbage: Color::srgb(0.5, 0.5, 0.5),
+        }
+    }
+
+    pub fn piece(&self, kind: TetrominoKind) -> Color {
+        self.pieces[kind as usize]
+    }
+
+    pub fn cell(&self, cell: Cell) -> Color {
+        match cell {
+            Cell::Empty => EMPTY_COLOR,
+            Cell::Filled(kind) => self.piece(kind),
+            Cell::Garbage => self.garbage,
+        }
+    }
+}
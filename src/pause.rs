@@ -0,0 +1,322 @@
+use crate::game::{CellSprite, Game, GameEventMessage, GameRules, restart_game};
+use crate::hud::NextPieceCell;
+use crate::input::{ActionState, Binding, InputAction, InputMap, InputQueue, Players, Rebinding};
+use crate::replays::ReplayRecording;
+use crate::settings::{PaletteChoice, Settings, WindowModeSetting};
+use crate::states::{AppState, PauseState};
+use bevy::input::gamepad::GamepadConnectionEvent;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+
+/// What [`hide_board`] hides: the stack and the next queue.
+type BoardContents = Or<(With<CellSprite>, With<NextPieceCell>)>;
+
+/// Root of whichever pause panel is open, so it can be cleaned up easily.
+#[derive(Component)]
+pub struct PauseRoot;
+
+/// What a button on the pause panels does when pressed.
+#[derive(Component, Clone, Copy)]
+pub enum PauseButton {
+    Resume,
+    Restart,
+    Settings,
+    QuitToMenu,
+    ToggleGhost,
+    CyclePalette,
+    CycleWindowMode,
+    CycleBoardSize,
+    Controls,
+    /// Wait for a key to add to (or remove from) this action.
+    Rebind(InputAction),
+    ResetControls,
+    Back,
+}
+
+/// The text of a pause panel button, rewritten when the setting it shows changes.
+#[derive(Component)]
+pub struct ButtonLabel(PauseButton);
+
+/// Toggle between running and paused with [`InputAction::Pause`]; from settings, go back to the
+/// pause menu. The rebinding screen takes every key, so it is left with its Back button.
+pub fn pause_input_system(
+    actions: Res<ActionState>,
+    state: Res<State<PauseState>>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    if !actions.just_pressed(InputAction::Pause) {
+        return;
+    }
+    match state.get() {
+        PauseState::Running => next_state.set(PauseState::Paused),
+        PauseState::Paused => next_state.set(PauseState::Running),
+        PauseState::Settings => next_state.set(PauseState::Paused),
+        PauseState::Controls => {}
+    }
+}
+
+/// Pause as soon as the window loses focus or a gamepad is unplugged, so the game never runs
+/// unattended.
+pub fn auto_pause_system(
+    mut focus_events: MessageReader<WindowFocused>,
+    mut connections: MessageReader<GamepadConnectionEvent>,
+    mut next_state: ResMut<NextState<PauseState>>,
+) {
+    let unfocused = focus_events.read().any(|event| !event.focused);
+    let unplugged = connections.read().any(|event| event.disconnected());
+    if unfocused || unplugged {
+        next_state.set(PauseState::Paused);
+    }
+}
+
+/// Drop input queued before the pause, so the first tick after resuming doesn't replay a
+/// press or a held direction the player has since let go of.
+pub fn clear_input_queue(mut queue: ResMut<InputQueue>) {
+    queue.clear();
+}
+
+/// Hide the board contents while paused, so a pause can't be used to plan ahead. The settings
+/// and controls menus only open from the pause menu, so they keep it hidden too; a game that
+/// ends leaves its final board on show under the Game Over screen.
+pub fn hide_board(mut cells: Query<&mut Visibility, BoardContents>) {
+    for mut visibility in &mut cells {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+pub fn show_board(mut cells: Query<&mut Visibility, BoardContents>) {
+    for mut visibility in &mut cells {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+pub fn spawn_pause_menu(commands: Commands, settings: Res<Settings>) {
+    spawn_panel(
+        commands,
+        "PAUSED",
+        &[
+            PauseButton::Resume,
+            PauseButton::Restart,
+            PauseButton::Settings,
+            PauseButton::QuitToMenu,
+        ],
+        &settings,
+    );
+}
+
+pub fn spawn_settings_menu(commands: Commands, settings: Res<Settings>) {
+    spawn_panel(
+        commands,
+        "SETTINGS",
+        &[
+            PauseButton::ToggleGhost,
+            PauseButton::CyclePalette,
+            PauseButton::CycleWindowMode,
+            PauseButton::CycleBoardSize,
+            PauseButton::Controls,
+            PauseButton::Back,
+        ],
+        &settings,
+    );
+}
+
+pub fn spawn_controls_menu(
+    commands: Commands,
+    settings: Res<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    rebinding.0 = None;
+    let mut buttons: Vec<_> = InputAction::ALL.map(PauseButton::Rebind).to_vec();
+    buttons.push(PauseButton::ResetControls);
+    buttons.push(PauseButton::Back);
+    spawn_panel(commands, "CONTROLS", &buttons, &settings);
+}
+
+/// A title over a column of buttons, centred on the screen.
+fn spawn_panel(mut commands: Commands, title: &str, buttons: &[PauseButton], settings: &Settings) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(20.0),
+                ..default()
+            },
+            PauseRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(title),
+                TextFont {
+                    font_size: 120.0,
+                    ..default()
+                },
+            ));
+            for &action in buttons {
+                parent
+                    .spawn((
+                        Node {
+                            min_width: Val::Px(280.0),
+                            padding: UiRect::horizontal(Val::Px(20.0)),
+                            height: Val::Px(64.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        Button,
+                        action,
+                        BackgroundColor(Color::srgb(0.2, 0.5, 0.8)),
+                    ))
+                    .with_children(|button| {
+                        button.spawn((
+                            Text::new(button_label(action, settings, None)),
+                            ButtonLabel(action),
+                        ));
+                    });
+            }
+        });
+}
+
+/// Despawn all entities that belong to a pause panel.
+pub fn cleanup_pause_menu(mut commands: Commands, roots: Query<Entity, With<PauseRoot>>) {
+    for entity in &roots {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn pause_button_system(
+    buttons: Query<(&Interaction, &PauseButton), Changed<Interaction>>,
+    mut game: ResMut<Game>,
+    mut recording: ResMut<ReplayRecording>,
+    rules: Res<GameRules>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut game_events: MessageWriter<GameEventMessage>,
+    state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    mut next_app_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            PauseButton::Resume => next_pause_state.set(PauseState::Running),
+            PauseButton::Restart => {
+                restart_game(&mut game, &mut recording, &rules, &mut game_events);
+                next_pause_state.set(PauseState::Running);
+            }
+            PauseButton::Settings => next_pause_state.set(PauseState::Settings),
+            PauseButton::QuitToMenu => next_app_state.set(AppState::MainMenu),
+            PauseButton::ToggleGhost => settings.visuals.ghost = !settings.visuals.ghost,
+            PauseButton::CyclePalette => {
+                settings.visuals.palette = match settings.visuals.palette {
+                    PaletteChoice::Guideline => PaletteChoice::ColorBlind,
+                    PaletteChoice::ColorBlind => PaletteChoice::Guideline,
+                }
+            }
+            PauseButton::CycleWindowMode => {
+                settings.window_mode = match settings.window_mode {
+                    WindowModeSetting::Windowed => WindowModeSetting::BorderlessFullscreen,
+                    WindowModeSetting::BorderlessFullscreen => WindowModeSetting::Fullscreen,
+                    WindowModeSetting::Fullscreen => WindowModeSetting::Windowed,
+                }
+            }
+            PauseButton::CycleBoardSize => settings.board_size = settings.board_size.next(),
+            PauseButton::Controls => next_pause_state.set(PauseState::Controls),
+            PauseButton::Rebind(action) => rebinding.0 = Some(*action),
+            PauseButton::ResetControls => settings.bindings = InputMap::default(),
+            PauseButton::Back => next_pause_state.set(match state.get() {
+                PauseState::Controls => PauseState::Settings,
+                _ => PauseState::Paused,
+            }),
+        }
+    }
+}
+
+/// While an action is waiting for input: Escape cancels, Backspace clears its bindings, a key
+/// or player 1 button it already has is removed, and any other is added (and taken from its
+/// previous action).
+pub fn rebind_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    players: Res<Players>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    // Skip the frame the button was pressed, so a gamepad confirm doesn't bind itself.
+    let Some(action) = rebinding.0.filter(|_| !rebinding.is_changed()) else {
+        return;
+    };
+    let gamepad = players
+        .gamepad(0)
+        .and_then(|entity| gamepads.get(entity).ok());
+    let binding = match keyboard_input.get_just_pressed().next() {
+        Some(&key) => Binding::Key(key),
+        None => match gamepad.and_then(|gamepad| gamepad.get_just_pressed().next()) {
+            Some(&button) => Binding::Button(button),
+            None => return,
+        },
+    };
+    let bindings = &mut settings.bindings;
+    match binding {
+        Binding::Key(KeyCode::Escape) => {}
+        Binding::Key(KeyCode::Backspace) => bindings.clear(action),
+        binding if bindings.bindings(action).contains(&binding) => bindings.unbind(action, binding),
+        binding => bindings.bind(action, binding),
+    }
+    rebinding.0 = None;
+}
+
+pub fn update_button_labels_system(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    mut texts: Query<(&ButtonLabel, &mut Text)>,
+) {
+    if !settings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (ButtonLabel(button), mut text) in &mut texts {
+        let label = button_label(*button, &settings, rebinding.0);
+        if text.0 != label {
+            text.0 = label;
+        }
+    }
+}
+
+fn button_label(button: PauseButton, settings: &Settings, waiting: Option<InputAction>) -> String {
+    match button {
+        PauseButton::Resume => "Resume".to_string(),
+        PauseButton::Restart => "Restart".to_string(),
+        PauseButton::Settings => "Settings".to_string(),
+        PauseButton::QuitToMenu => "Quit to Menu".to_string(),
+        PauseButton::ToggleGhost if settings.visuals.ghost => "Ghost: On".to_string(),
+        PauseButton::ToggleGhost => "Ghost: Off".to_string(),
+        PauseButton::CyclePalette => match settings.visuals.palette {
+            PaletteChoice::Guideline => "Colours: Guideline".to_string(),
+            PaletteChoice::ColorBlind => "Colours: Colour-blind".to_string(),
+        },
+        PauseButton::CycleWindowMode => match settings.window_mode {
+            WindowModeSetting::Windowed => "Window: Windowed".to_string(),
+            WindowModeSetting::BorderlessFullscreen => "Window: Borderless".to_string(),
+            WindowModeSetting::Fullscreen => "Window: Fullscreen".to_string(),
+        },
+        PauseButton::CycleBoardSize => {
+            let (width, height) = settings.board_size.dimensions();
+            format!("Board: {width}x{height} (next game)")
+        }
+        PauseButton::Controls => "Controls".to_string(),
+        PauseButton::Rebind(action) if waiting == Some(action) => {
+            format!("{}: press a key...", action.label())
+        }
+        PauseButton::Rebind(action) => {
+            format!("{}: {}", action.label(), settings.bindings.describe(action))
+        }
+        PauseButton::ResetControls => "Reset to Defaults".to_string(),
+        PauseButton::Back => "Back".to_string(),
+    }
+}
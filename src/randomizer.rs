@@ -0,0 +1,153 @@
+use crate::tetrominoes::TetrominoKind;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// How many upcoming pieces are always decided ahead of time and visible through
+/// [`PieceRandomizer::peek`].
+pub const LOOKAHEAD: usize = 6;
+
+/// How the next piece kinds are drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RandomizerMode {
+    /// Deal all seven kinds in a shuffled bag, then refill: no droughts longer than 12 pieces.
+    #[default]
+    Bag7,
+    /// Every kind is equally likely on every draw.
+    Uniform,
+}
+
+/// Seeded source of piece kinds. Two randomizers built from the same seed and mode deal
+/// exactly the same sequence, so replays and versus games can share it.
+#[derive(Clone, Debug)]
+pub struct PieceRandomizer {
+    seed: u64,
+    mode: RandomizerMode,
+    rng: SplitMix64,
+    bag: Vec<TetrominoKind>,
+    queue: VecDeque<TetrominoKind>,
+}
+
+impl PieceRandomizer {
+    /// A 7-bag randomizer.
+    pub fn new(seed: u64) -> Self {
+        Self::with_mode(seed, RandomizerMode::Bag7)
+    }
+
+    pub fn with_mode(seed: u64, mode: RandomizerMode) -> Self {
+        let mut randomizer = Self {
+            seed,
+            mode,
+            rng: SplitMix64(seed),
+            bag: Vec::with_capacity(TetrominoKind::ALL.len()),
+            queue: VecDeque::with_capacity(LOOKAHEAD + 1),
+        };
+        randomizer.fill_queue();
+        randomizer
+    }
+
+    /// A 7-bag randomizer with a seed taken from the OS.
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn mode(&self) -> RandomizerMode {
+        self.mode
+    }
+
+    /// The next [`LOOKAHEAD`] kinds, in the order they will be dealt.
+    pub fn peek(&self) -> impl Iterator<Item = TetrominoKind> + '_ {
+        self.queue.iter().copied()
+    }
+
+    /// Deal the next kind and decide one more at the back of the queue.
+    pub fn next_kind(&mut self) -> TetrominoKind {
+        let kind = self.queue.pop_front().unwrap_or_else(|| self.draw());
+        self.fill_queue();
+        kind
+    }
+
+    fn fill_queue(&mut self) {
+        while self.queue.len() < LOOKAHEAD {
+            let kind = self.draw();
+            self.queue.push_back(kind);
+        }
+    }
+
+    fn draw(&mut self) -> TetrominoKind {
+        match self.mode {
+            RandomizerMode::Uniform => TetrominoKind::ALL[self.rng.below(TetrominoKind::ALL.len())],
+            RandomizerMode::Bag7 => {
+                if self.bag.is_empty() {
+                    self.bag.extend(TetrominoKind::ALL);
+                    // Fisher-Yates; the bag is dealt from the back.
+                    for i in (1..self.bag.len()).rev() {
+                        let j = self.rng.below(i + 1);
+                        self.bag.swap(i, j);
+                    }
+                }
+                self.bag.pop().expect("bag was just refilled")
+            }
+        }
+    }
+}
+
+/// SplitMix64. Tiny and fully specified, so a seed deals the same pieces on every platform and
+/// regardless of `rand` upgrades, which matters for saved replays.
+#[derive(Clone, Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform-enough index in `0..n` for the tiny `n` used here.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deal(randomizer: &mut PieceRandomizer, count: usize) -> Vec<TetrominoKind> {
+        (0..count).map(|_| randomizer.next_kind()).collect()
+    }
+
+    #[test]
+    fn same_seed_deals_the_same_sequence() {
+        for mode in [RandomizerMode::Bag7, RandomizerMode::Uniform] {
+            let mut a = PieceRandomizer::with_mode(42, mode);
+            let mut b = PieceRandomizer::with_mode(42, mode);
+            assert_eq!(deal(&mut a, 200), deal(&mut b, 200));
+        }
+    }
+
+    #[test]
+    fn peek_shows_what_is_dealt_next() {
+        let mut randomizer = PieceRandomizer::new(7);
+        let upcoming: Vec<_> = randomizer.peek().collect();
+        assert_eq!(upcoming.len(), LOOKAHEAD);
+        assert_eq!(deal(&mut randomizer, LOOKAHEAD), upcoming);
+    }
+
+    #[test]
+    fn every_bag_holds_each_kind_once() {
+        let mut randomizer = PieceRandomizer::new(1234);
+        let pieces = deal(&mut randomizer, 7 * 50);
+        for bag in pieces.chunks(7) {
+            for kind in TetrominoKind::ALL {
+                assert_eq!(bag.iter().filter(|&&dealt| dealt == kind).count(), 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use crate::engine::{
+    Action, GameConfig, GameEvent, GameState, Handling, Inputs, SoftDrop, TICK, TICK_RATE,
+};
+use crate::randomizer::{PieceRandomizer, RandomizerMode};
+use serde::{Deserialize, Serialize};
+
+/// Format version written to new replays. Files with any other version are refused rather than
+/// played back wrongly.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// Everything needed to play a game again exactly as it happened: the seed and rules it started
+/// with, how many [`GameState::tick`]s it lasted, and the input fed to the ticks where it
+/// changed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub randomizer: RandomizerMode,
+    pub config: GameConfig,
+    /// Ticks played, each [`TICK`] seconds long.
+    pub ticks: u32,
+    /// Input at every tick that pressed something or changed what is held, in tick order.
+    /// Ticks in between hold whatever the previous record held.
+    pub inputs: Vec<InputRecord>,
+    /// Handling the player changed mid-game, applied before the tick it is recorded at.
+    pub handling: Vec<HandlingRecord>,
+    /// Final score and lines, so a list of replays can show them without playing each one.
+    pub score: u32,
+    pub lines: u32,
+}
+
+/// The actions pressed and held at one tick of a [`Replay`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputRecord {
+    /// Ticks since the start; the input happened `tick / TICK_RATE` seconds in.
+    pub tick: u32,
+    pub pressed: Vec<Action>,
+    pub held: Vec<Action>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HandlingRecord {
+    pub tick: u32,
+    pub handling: Handling,
+    pub soft_drop: SoftDrop,
+}
+
+impl Replay {
+    /// Parse a replay written by [`ron`], refusing other format versions.
+    pub fn from_ron(text: &str) -> Result<Self, String> {
+        let replay: Self = ron::from_str(text).map_err(|error| error.to_string())?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "replay version {} is not supported (expected {REPLAY_VERSION})",
+                replay.version
+            ));
+        }
+        Ok(replay)
+    }
+
+    /// The game as it was before the first tick.
+    pub fn new_game(&self) -> GameState {
+        GameState::with_config(
+            PieceRandomizer::with_mode(self.seed, self.randomizer),
+            self.config.clone(),
+        )
+    }
+
+    /// Seconds of play recorded.
+    pub fn duration(&self) -> f32 {
+        self.ticks as f32 / TICK_RATE as f32
+    }
+}
+
+/// Builds a [`Replay`] of a live game one tick at a time.
+#[derive(Clone, Debug)]
+pub struct ReplayRecorder {
+    replay: Replay,
+    held: Vec<Action>,
+}
+
+impl ReplayRecorder {
+    /// Start recording `game`, which must not have been ticked yet.
+    pub fn new(game: &GameState) -> Self {
+        Self {
+            replay: Replay {
+                version: REPLAY_VERSION,
+                seed: game.randomizer.seed(),
+                randomizer: game.randomizer.mode(),
+                config: game.config.clone(),
+                ticks: 0,
+                inputs: Vec::new(),
+                handling: Vec::new(),
+                score: 0,
+                lines: 0,
+            },
+            held: Vec::new(),
+        }
+    }
+
+    /// Note the tick `game` is about to take. Call it right before [`GameState::tick`].
+    pub fn record(&mut self, game: &GameState, inputs: &Inputs) {
+        if game.is_game_over() {
+            return;
+        }
+        let tick = self.replay.ticks;
+        let (handling, soft_drop) = self.current_handling();
+        if *handling != game.config.handling || soft_drop != game.config.soft_drop {
+            self.replay.handling.push(HandlingRecord {
+                tick,
+                handling: game.config.handling.clone(),
+                soft_drop: game.config.soft_drop,
+            });
+        }
+        if !inputs.pressed.is_empty() || inputs.held != self.held {
+            self.held.clone_from(&inputs.held);
+            self.replay.inputs.push(InputRecord {
+                tick,
+                pressed: inputs.pressed.clone(),
+                held: inputs.held.clone(),
+            });
+        }
+        self.replay.ticks += 1;
+    }
+
+    /// Whether the player did anything worth keeping.
+    pub fn is_empty(&self) -> bool {
+        self.replay.inputs.is_empty()
+    }
+
+    /// The replay so far, with the result of `game` as its final score.
+    pub fn finish(&self, game: &GameState) -> Replay {
+        let mut replay = self.replay.clone();
+        replay.score = game.score.points;
+        replay.lines = game.lines;
+        replay
+    }
+
+    fn current_handling(&self) -> (&Handling, SoftDrop) {
+        match self.replay.handling.last() {
+            Some(record) => (&record.handling, record.soft_drop),
+            None => (&self.replay.config.handling, self.replay.config.soft_drop),
+        }
+    }
+}
+
+/// Plays a [`Replay`] back through [`GameState::tick`] at any speed, with seeking.
+#[derive(Clone, Debug)]
+pub struct ReplayPlayer {
+    replay: Replay,
+    /// Next tick to play.
+    tick: u32,
+    next_input: usize,
+    next_handling: usize,
+    held: Vec<Action>,
+    /// Seconds banked towards the next tick.
+    banked: f32,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            tick: 0,
+            next_input: 0,
+            next_handling: 0,
+            held: Vec::new(),
+            banked: 0.0,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Recorded seconds played so far.
+    pub fn time(&self) -> f32 {
+        self.tick as f32 / TICK_RATE as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick >= self.replay.ticks
+    }
+
+    /// Play every tick that fits in `seconds` more of playback on top of what is already banked.
+    pub fn advance(&mut self, game: &mut GameState, seconds: f32) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        self.banked += seconds;
+        while !self.is_finished() && self.banked >= TICK {
+            self.banked -= TICK;
+            events.extend(self.play_tick(game));
+        }
+        if self.is_finished() {
+            self.banked = 0.0;
+        }
+        events
+    }
+
+    /// Play silently up to `time` recorded seconds in. Seeking backwards puts `game` back to
+    /// the start first, since ticks can't be undone.
+    pub fn seek(&mut self, game: &mut GameState, time: f32) {
+        let target = ((time.max(0.0) * TICK_RATE as f32).round() as u32).min(self.replay.ticks);
+        if target < self.tick {
+            *game = self.replay.new_game();
+            self.tick = 0;
+            self.next_input = 0;
+            self.next_handling = 0;
+            self.held.clear();
+        }
+        self.banked = 0.0;
+        while self.tick < target {
+            self.play_tick(game);
+        }
+    }
+
+    fn play_tick(&mut self, game: &mut GameState) -> Vec<GameEvent> {
+        let tick = self.tick;
+        if let Some(record) = self.replay.handling.get(self.next_handling)
+            && record.tick == tick
+        {
+            game.config.handling = record.handling.clone();
+            game.config.soft_drop = record.soft_drop;
+            self.next_handling += 1;
+        }
+        let mut inputs = Inputs {
+            pressed: Vec::new(),
+            held: self.held.clone(),
+        };
+        if let Some(record) = self.replay.inputs.get(self.next_input)
+            && record.tick == tick
+        {
+            inputs.pressed.clone_from(&record.pressed);
+            inputs.held.clone_from(&record.held);
+            self.held.clone_from(&record.held);
+            self.next_input += 1;
+        }
+        self.tick += 1;
+        game.tick(&inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs for `tick` of a scripted game: every second a piece is turned, tapped or auto
+    /// shifted to a column, maybe soft dropped or held, then hard dropped.
+    fn scripted_inputs(tick: u32) -> Inputs {
+        let (piece, t) = (tick / 60, tick % 60);
+        let offset = (piece * 7 % 11) as i32 - 5;
+        let shift = if offset < 0 {
+            Action::MoveLeft
+        } else {
+            Action::MoveRight
+        };
+        let mut pressed = Vec::new();
+        let mut held = Vec::new();
+        match t {
+            2 if piece % 4 == 1 => pressed.push(Action::RotateCw),
+            2 if piece % 4 == 2 => pressed.push(Action::Rotate180),
+            2 if piece % 4 == 3 => pressed.push(Action::RotateCcw),
+            3 if piece % 13 == 6 => pressed.push(Action::Hold),
+            59 => pressed.push(Action::HardDrop),
+            _ => {}
+        }
+        if offset.abs() == 5 && t >= 10 {
+            held.push(shift);
+        }
+        if offset.abs() < 5 && t >= 10 && t < 10 + 2 * offset.unsigned_abs() && t % 2 == 0 {
+            pressed.push(shift);
+        }
+        if t == 10 && offset.abs() == 5 {
+            pressed.push(shift);
+        }
+        if piece % 3 == 2 && t > 40 {
+            held.push(Action::SoftDrop);
+        }
+        held.extend(pressed.iter().copied());
+        Inputs { pressed, held }
+    }
+
+    #[test]
+    fn played_back_replay_ends_in_the_recorded_state() {
+        // A seed this script clears lines with, so playback covers line clears and scoring.
+        let mut game = GameState::new(PieceRandomizer::new(1841));
+        let mut recorder = ReplayRecorder::new(&game);
+        for tick in 0..5000 {
+            if tick == 600 {
+                game.config.handling = Handling { das: 6, arr: 0 };
+                game.config.soft_drop = SoftDrop::Sonic;
+            }
+            let inputs = scripted_inputs(tick);
+            recorder.record(&game, &inputs);
+            game.tick(&inputs);
+        }
+
+        assert!(game.lines > 0);
+
+        let text = ron::to_string(&recorder.finish(&game)).unwrap();
+        let replay = Replay::from_ron(&text).unwrap();
+        assert_eq!(replay.score, game.score.points);
+
+        let mut played = replay.new_game();
+        let mut player = ReplayPlayer::new(replay);
+        player.advance(&mut played, player.replay().duration() + 1.0);
+        assert!(player.is_finished());
+        assert_eq!(format!("{played:?}"), format!("{game:?}"));
+    }
+
+    #[test]
+    fn seeking_back_replays_from_the_start() {
+        let mut game = GameState::new(PieceRandomizer::new(99));
+        let mut recorder = ReplayRecorder::new(&game);
+        for tick in 0..1200 {
+            let inputs = scripted_inputs(tick);
+            recorder.record(&game, &inputs);
+            game.tick(&inputs);
+        }
+
+        let replay = recorder.finish(&game);
+        let mut played = replay.new_game();
+        let mut player = ReplayPlayer::new(replay);
+        player.seek(&mut played, 15.0);
+        player.seek(&mut played, 5.0);
+        player.seek(&mut played, player.replay().duration());
+        assert_eq!(format!("{played:?}"), format!("{game:?}"));
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let game = GameState::new(PieceRandomizer::new(1));
+        let mut replay = ReplayRecorder::new(&game).finish(&game);
+        replay.version = REPLAY_VERSION + 1;
+        let text = ron::to_string(&replay).unwrap();
+        assert!(Replay::from_ron(&text).is_err());
+    }
+}
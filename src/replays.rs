@@ -0,0 +1,469 @@
+use crate::game::{Game, GameEventMessage, InGameRoot};
+use crate::highscores::civil_from_days;
+use crate::input::{ActionState, InputAction};
+use crate::settings::{config_file, write_ron};
+use crate::states::AppState;
+use bevy::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tetris::engine::{GameEvent, GameState};
+use tetris::replay::{Replay, ReplayPlayer, ReplayRecorder};
+
+/// Directory under the config dir that replays are saved in, one file per game.
+const REPLAYS_DIR: &str = "replays";
+
+/// Newest replays listed on the replay screen.
+const LIST_SIZE: usize = 10;
+
+/// Playback speeds the viewer steps through.
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+const NORMAL_SPEED: usize = 2;
+
+/// Seconds skipped by one seek.
+const SEEK_SECONDS: f32 = 5.0;
+
+/// Recording of the game in progress, saved when it ends, is restarted or is quit.
+#[derive(Resource, Deref, DerefMut)]
+pub struct ReplayRecording(pub ReplayRecorder);
+
+impl ReplayRecording {
+    pub fn new(game: &GameState) -> Self {
+        Self(ReplayRecorder::new(game))
+    }
+}
+
+pub fn replays_dir() -> Option<PathBuf> {
+    config_file(REPLAYS_DIR)
+}
+
+/// Write `recording` to a new file named after the current time, unless nothing was played.
+pub fn save_replay(recording: &ReplayRecording, game: &GameState) {
+    if recording.is_empty() {
+        return;
+    }
+    let Some(dir) = replays_dir() else {
+        warn!("No config directory to save replays in");
+        return;
+    };
+    let path = dir.join(format!("{}.ron", timestamp()));
+    if let Err(error) = write_ron(&path, &recording.finish(game)) {
+        warn!("{error}");
+    }
+}
+
+/// Save the game just left, whether it ended or was quit.
+pub fn save_replay_system(recording: Option<Res<ReplayRecording>>, game: Option<Res<Game>>) {
+    if let (Some(recording), Some(game)) = (recording, game) {
+        save_replay(&recording, &game);
+    }
+}
+
+/// The current UTC time as `YYYY-MM-DD_HH-MM-SS_mmm`, which sorts in time order.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time_of_day = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}_{:03}",
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+        now.subsec_millis()
+    )
+}
+
+/// The replays shown on the replay screen, newest first, with the file name each came from.
+#[derive(Resource, Default)]
+pub struct ReplayList(Vec<(String, Replay)>);
+
+#[derive(Component)]
+pub struct ReplayBrowserRoot;
+
+/// Watch the replay at this index of [`ReplayList`].
+#[derive(Component)]
+pub struct ReplayFileButton(usize);
+
+#[derive(Component)]
+pub struct ReplaysBackButton;
+
+/// Read the newest [`LIST_SIZE`] replays. Files that can't be read are reported by name.
+fn load_replays() -> (Vec<(String, Replay)>, Vec<String>) {
+    let mut paths: Vec<PathBuf> = replays_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+        .collect();
+    paths.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut replays = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths.into_iter().take(LIST_SIZE) {
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let loaded = fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| Replay::from_ron(&text));
+        match loaded {
+            Ok(replay) => replays.push((name, replay)),
+            Err(error) => errors.push(format!("{name}: {error}")),
+        }
+    }
+    (replays, errors)
+}
+
+pub fn spawn_replay_browser(mut commands: Commands) {
+    let (replays, errors) = load_replays();
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(20.0),
+                ..default()
+            },
+            ReplayBrowserRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("REPLAYS"),
+                TextFont {
+                    font_size: 120.0,
+                    ..default()
+                },
+            ));
+            if replays.is_empty() {
+                parent.spawn(Text::new("No replays yet"));
+            }
+            for (index, (name, replay)) in replays.iter().enumerate() {
+                let seconds = replay.duration() as u32;
+                parent
+                    .spawn((
+                        Node {
+                            min_width: Val::Px(280.0),
+                            padding: UiRect::horizontal(Val::Px(20.0)),
+                            height: Val::Px(64.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        Button,
+                        ReplayFileButton(index),
+                        BackgroundColor(Color::srgb(0.2, 0.5, 0.8)),
+                    ))
+                    .with_children(|button| {
+                        button.spawn(Text::new(format!(
+                            "{name}   {} pts   {} lines   {}:{:02}",
+                            replay.score,
+                            replay.lines,
+                            seconds / 60,
+                            seconds % 60
+                        )));
+                    });
+            }
+            for error in &errors {
+                parent.spawn((
+                    Text::new(error.clone()),
+                    TextFont {
+                        font_size: 24.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.9, 0.1, 0.1)),
+                    Node {
+                        max_width: Val::Px(900.0),
+                        ..default()
+                    },
+                ));
+            }
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(220.0),
+                        height: Val::Px(64.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    Button,
+                    ReplaysBackButton,
+                    BackgroundColor(Color::srgb(0.2, 0.5, 0.8)),
+                ))
+                .with_children(|button| {
+                    button.spawn(Text::new("Back"));
+                });
+        });
+    commands.insert_resource(ReplayList(replays));
+}
+
+pub fn cleanup_replay_browser(
+    mut commands: Commands,
+    roots: Query<Entity, With<ReplayBrowserRoot>>,
+) {
+    for entity in &roots {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<ReplayList>();
+}
+
+/// Open the chosen replay in the viewer, or go back to the main menu with the Back button or
+/// [`InputAction::Pause`].
+pub fn replay_browser_system(
+    mut commands: Commands,
+    files: Query<(&Interaction, &ReplayFileButton), Changed<Interaction>>,
+    back: Query<&Interaction, (Changed<Interaction>, With<ReplaysBackButton>)>,
+    actions: Res<ActionState>,
+    list: Res<ReplayList>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, ReplayFileButton(index)) in &files {
+        if *interaction == Interaction::Pressed
+            && let Some((_, replay)) = list.0.get(*index)
+        {
+            commands.insert_resource(ReplayViewer::new(replay.clone()));
+            next_state.set(AppState::ReplayViewer);
+            return;
+        }
+    }
+    let pressed = back
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed);
+    if pressed || actions.just_pressed(InputAction::Pause) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+/// The replay being watched and how it is being played.
+#[derive(Resource)]
+pub struct ReplayViewer {
+    pub player: ReplayPlayer,
+    /// Index into [`SPEEDS`].
+    speed: usize,
+    paused: bool,
+}
+
+impl ReplayViewer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            player: ReplayPlayer::new(replay),
+            speed: NORMAL_SPEED,
+            paused: false,
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        SPEEDS[self.speed]
+    }
+}
+
+/// A button on the viewer's control bar.
+#[derive(Component, Clone, Copy)]
+pub enum ReplayControl {
+    SeekBack,
+    PlayPause,
+    SeekForward,
+    Slower,
+    Faster,
+    Exit,
+}
+
+impl ReplayControl {
+    const ALL: [ReplayControl; 6] = [
+        ReplayControl::SeekBack,
+        ReplayControl::PlayPause,
+        ReplayControl::SeekForward,
+        ReplayControl::Slower,
+        ReplayControl::Faster,
+        ReplayControl::Exit,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ReplayControl::SeekBack => "-5s",
+            ReplayControl::PlayPause => "Play/Pause",
+            ReplayControl::SeekForward => "+5s",
+            ReplayControl::Slower => "Slower",
+            ReplayControl::Faster => "Faster",
+            ReplayControl::Exit => "Back",
+        }
+    }
+
+    /// The action that works as a shortcut for this control, if any.
+    fn shortcut(self) -> Option<InputAction> {
+        match self {
+            ReplayControl::SeekBack => Some(InputAction::MoveLeft),
+            ReplayControl::PlayPause => Some(InputAction::Pause),
+            ReplayControl::SeekForward => Some(InputAction::MoveRight),
+            ReplayControl::Slower => Some(InputAction::SoftDrop),
+            ReplayControl::Faster => Some(InputAction::HardDrop),
+            ReplayControl::Exit => None,
+        }
+    }
+}
+
+/// Time, speed and whether playback is paused.
+#[derive(Component)]
+pub struct ReplayStatusText;
+
+/// Put the replay's starting position on the board.
+pub fn start_replay(mut commands: Commands, viewer: Res<ReplayViewer>) {
+    commands.insert_resource(Game(viewer.player.replay().new_game()));
+}
+
+/// The status line and control bar over the board. Part of [`InGameRoot`], so it goes with it.
+pub fn spawn_replay_controls(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(20.0),
+                left: Val::Px(20.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            InGameRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 40.0,
+                    ..default()
+                },
+                ReplayStatusText,
+            ));
+            parent
+                .spawn(Node {
+                    column_gap: Val::Px(8.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    for control in ReplayControl::ALL {
+                        row.spawn((
+                            Node {
+                                padding: UiRect::horizontal(Val::Px(16.0)),
+                                height: Val::Px(56.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            Button,
+                            control,
+                            BackgroundColor(Color::srgb(0.2, 0.5, 0.8)),
+                        ))
+                        .with_children(|button| {
+                            button.spawn(Text::new(control.label()));
+                        });
+                    }
+                });
+        });
+}
+
+/// Play the recorded steps that fit in this frame at the chosen speed, pausing at the end.
+pub fn replay_playback_system(
+    time: Res<Time>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut game: ResMut<Game>,
+    mut game_events: MessageWriter<GameEventMessage>,
+) {
+    if viewer.paused {
+        return;
+    }
+    let seconds = time.delta_secs() * viewer.speed();
+    let events = viewer.player.advance(&mut game, seconds);
+    game_events.write_batch(events.into_iter().map(GameEventMessage));
+    if viewer.player.is_finished() {
+        viewer.paused = true;
+    }
+}
+
+/// Apply the control bar buttons and their [`ReplayControl::shortcut`]s.
+pub fn replay_controls_system(
+    buttons: Query<(&Interaction, &ReplayControl), Changed<Interaction>>,
+    actions: Res<ActionState>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut game: ResMut<Game>,
+    mut game_events: MessageWriter<GameEventMessage>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let clicked = buttons
+        .iter()
+        .filter(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, control)| *control);
+    let shortcuts = ReplayControl::ALL.into_iter().filter(|control| {
+        control
+            .shortcut()
+            .is_some_and(|action| actions.just_pressed(action))
+    });
+    for control in clicked.chain(shortcuts) {
+        let viewer = &mut *viewer;
+        let seek_to = match control {
+            ReplayControl::SeekBack => Some(viewer.player.time() - SEEK_SECONDS),
+            ReplayControl::SeekForward => Some(viewer.player.time() + SEEK_SECONDS),
+            // Playing a finished replay starts it again.
+            ReplayControl::PlayPause if viewer.player.is_finished() => {
+                viewer.paused = false;
+                Some(0.0)
+            }
+            ReplayControl::PlayPause => {
+                viewer.paused = !viewer.paused;
+                None
+            }
+            ReplayControl::Slower => {
+                viewer.speed = viewer.speed.saturating_sub(1);
+                None
+            }
+            ReplayControl::Faster => {
+                viewer.speed = (viewer.speed + 1).min(SPEEDS.len() - 1);
+                None
+            }
+            ReplayControl::Exit => {
+                next_state.set(AppState::Replays);
+                None
+            }
+        };
+        if let Some(time) = seek_to {
+            viewer.player.seek(&mut game, time.max(0.0));
+            // Let the HUD redraw the queue and hold slot for the new position.
+            game_events.write(GameEventMessage(GameEvent::PieceSpawned(game.active.kind)));
+        }
+    }
+}
+
+pub fn update_replay_status_system(
+    viewer: Res<ReplayViewer>,
+    mut texts: Query<&mut Text, With<ReplayStatusText>>,
+) {
+    let clock = |seconds: f32| {
+        let seconds = seconds as u32;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    };
+    let state = if viewer.player.is_finished() {
+        "  END"
+    } else if viewer.paused {
+        "  PAUSED"
+    } else {
+        ""
+    };
+    let label = format!(
+        "{} / {}  x{}{state}",
+        clock(viewer.player.time()),
+        clock(viewer.player.replay().duration()),
+        viewer.speed()
+    );
+    for mut text in &mut texts {
+        if text.0 != label {
+            text.0 = label.clone();
+        }
+    }
+}
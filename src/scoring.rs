@@ -0,0 +1,133 @@
+/// Guideline points per row for soft drop.
+const SOFT_DROP_POINTS_PER_ROW: u32 = 1;
+
+/// Guideline points per row for hard drop.
+const HARD_DROP_POINTS_PER_ROW: u32 = 2;
+
+/// Guideline combo bonus, per combo step and level.
+const COMBO_POINTS: u32 = 50;
+
+/// How the last piece was spun into place, for pieces locked right after a rotation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TSpin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+/// Running score of a game, following the guideline scoring table.
+#[derive(Clone, Debug, Default)]
+pub struct Score {
+    pub points: u32,
+    /// Locks in a row that cleared lines, minus one; `None` once a lock clears nothing.
+    pub combo: Option<u32>,
+    /// Whether the last line clear was a difficult one (a tetris or a T-spin clear), so the
+    /// next difficult clear earns the back-to-back bonus.
+    pub back_to_back: bool,
+}
+
+impl Score {
+    pub fn soft_drop(&mut self, rows: u32) {
+        self.points += rows * SOFT_DROP_POINTS_PER_ROW;
+    }
+
+    pub fn hard_drop(&mut self, rows: u32) {
+        self.points += rows * HARD_DROP_POINTS_PER_ROW;
+    }
+
+    /// Score a locked piece that cleared `lines` rows at `level`, with `spin` the T-spin it
+    /// was locked with. Returns the points awarded.
+    pub fn lock(&mut self, lines: u32, spin: TSpin, level: u32) -> u32 {
+        let base = match (spin, lines) {
+            (TSpin::None, 0) => 0,
+            (TSpin::None, 1) => 100,
+            (TSpin::None, 2) => 300,
+            (TSpin::None, 3) => 500,
+            (TSpin::None, _) => 800,
+            (TSpin::Mini, 0) => 100,
+            (TSpin::Mini, 1) => 200,
+            (TSpin::Mini, _) => 400,
+            (TSpin::Full, 0) => 400,
+            (TSpin::Full, 1) => 800,
+            (TSpin::Full, 2) => 1200,
+            (TSpin::Full, _) => 1600,
+        } * level;
+
+        if lines == 0 {
+            // A spin without lines scores but leaves back-to-back and combo chains alone,
+            // except that any lock without lines ends the combo.
+            self.combo = None;
+            self.points += base;
+            return base;
+        }
+
+        let difficult = lines >= 4 || spin != TSpin::None;
+        let mut awarded = if difficult && self.back_to_back {
+            base * 3 / 2
+        } else {
+            base
+        };
+        self.back_to_back = difficult;
+
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        awarded += COMBO_POINTS * combo * level;
+        self.combo = Some(combo);
+
+        self.points += awarded;
+        awarded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_clears_follow_the_guideline_table() {
+        for (lines, points) in [(1, 100), (2, 300), (3, 500), (4, 800)] {
+            assert_eq!(Score::default().lock(lines, TSpin::None, 1), points);
+        }
+        assert_eq!(Score::default().lock(0, TSpin::Mini, 1), 100);
+        assert_eq!(Score::default().lock(1, TSpin::Mini, 1), 200);
+        assert_eq!(Score::default().lock(0, TSpin::Full, 1), 400);
+        assert_eq!(Score::default().lock(2, TSpin::Full, 1), 1200);
+        assert_eq!(Score::default().lock(3, TSpin::Full, 1), 1600);
+        assert_eq!(Score::default().lock(4, TSpin::None, 3), 2400);
+    }
+
+    #[test]
+    fn difficult_clears_in_a_row_earn_back_to_back() {
+        let mut score = Score::default();
+        assert_eq!(score.lock(4, TSpin::None, 1), 800);
+        score.lock(0, TSpin::None, 1);
+        // A lock without lines breaks the combo but keeps back-to-back alive.
+        assert_eq!(score.lock(2, TSpin::Full, 1), 1800);
+        score.lock(0, TSpin::None, 1);
+        assert_eq!(score.lock(1, TSpin::None, 1), 100);
+        assert!(!score.back_to_back);
+        score.lock(0, TSpin::None, 1);
+        assert_eq!(score.lock(4, TSpin::None, 1), 800);
+    }
+
+    #[test]
+    fn consecutive_clears_build_a_combo() {
+        let mut score = Score::default();
+        assert_eq!(score.lock(1, TSpin::None, 2), 200);
+        assert_eq!(score.lock(1, TSpin::None, 2), 300);
+        assert_eq!(score.lock(2, TSpin::None, 2), 800);
+        assert_eq!(score.combo, Some(2));
+        score.lock(0, TSpin::None, 2);
+        assert_eq!(score.combo, None);
+        assert_eq!(score.lock(1, TSpin::None, 2), 200);
+        assert_eq!(score.points, 200 + 300 + 800 + 200);
+    }
+
+    #[test]
+    fn drops_score_per_row() {
+        let mut score = Score::default();
+        score.soft_drop(3);
+        score.hard_drop(5);
+        assert_eq!(score.points, 13);
+    }
+}
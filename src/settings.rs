@@ -0,0 +1,330 @@
+use crate::game::{Game, GameRules, GhostConfig};
+use crate::hud::PreviewConfig;
+use crate::input::InputMap;
+use crate::palette::Palette;
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PrimaryWindow, VideoModeSelection, WindowMode};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tetris::board::{BOARD_WIDTH, VISIBLE_HEIGHT};
+use tetris::engine::{ARR, DAS, Handling, SOFT_DROP_FACTOR, SoftDrop, ms_to_ticks, ticks_to_ms};
+use tetris::randomizer::LOOKAHEAD;
+
+/// Directory under the user's config dir that holds our files.
+const APP_DIR: &str = "tet.rs";
+const SETTINGS_FILE: &str = "settings.ron";
+/// Where a settings file that couldn't be loaded is copied before it is overwritten.
+pub const SETTINGS_BACKUP: &str = "settings.ron.bak";
+
+/// Everything the player can configure, saved to [`settings_path`] whenever it changes.
+/// Missing keys in the file fall back to their defaults and out-of-range values are clamped.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub handling: HandlingSettings,
+    pub bindings: InputMap,
+    pub audio: AudioSettings,
+    pub visuals: VisualSettings,
+    pub window_mode: WindowModeSetting,
+    /// Board new games are played on; a game in progress keeps its own.
+    pub board_size: BoardSize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HandlingSettings {
+    /// Delayed auto shift in milliseconds.
+    pub das_ms: f32,
+    /// Auto repeat rate in milliseconds; 0 moves the piece straight to the wall.
+    pub arr_ms: f32,
+    /// How many times faster than gravity a soft drop falls.
+    pub soft_drop_factor: f32,
+    /// Soft drop straight to the floor instead of by [`Self::soft_drop_factor`].
+    pub sonic_drop: bool,
+}
+
+impl Default for HandlingSettings {
+    fn default() -> Self {
+        Self {
+            das_ms: ticks_to_ms(DAS),
+            arr_ms: ticks_to_ms(ARR),
+            soft_drop_factor: SOFT_DROP_FACTOR as f32,
+            sonic_drop: false,
+        }
+    }
+}
+
+/// Volumes from 0.0 (muted) to 1.0.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub music: f32,
+    pub effects: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            music: 0.8,
+            effects: 0.8,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct VisualSettings {
+    pub ghost: bool,
+    /// Opacity of the ghost piece, 0.0 to 1.0.
+    pub ghost_alpha: f32,
+    pub palette: PaletteChoice,
+    /// Upcoming pieces shown in the HUD, 1 to [`LOOKAHEAD`].
+    pub next_count: usize,
+}
+
+impl Default for VisualSettings {
+    fn default() -> Self {
+        Self {
+            ghost: true,
+            ghost_alpha: 0.3,
+            palette: PaletteChoice::Guideline,
+            next_count: 5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PaletteChoice {
+    #[default]
+    Guideline,
+    ColorBlind,
+}
+
+impl PaletteChoice {
+    pub fn palette(self) -> Palette {
+        match self {
+            PaletteChoice::Guideline => Palette::guideline(),
+            PaletteChoice::ColorBlind => Palette::color_blind(),
+        }
+    }
+}
+
+/// Board sizes on offer, as columns by visible rows.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BoardSize {
+    /// The guideline 10x20.
+    #[default]
+    Standard,
+    /// 4x20, for I pieces and nothing else.
+    Narrow,
+    /// 12x20.
+    Wide,
+    /// 10x40.
+    Tall,
+}
+
+impl BoardSize {
+    /// Columns and visible rows.
+    pub fn dimensions(self) -> (usize, usize) {
+        match self {
+            BoardSize::Standard => (BOARD_WIDTH, VISIBLE_HEIGHT),
+            BoardSize::Narrow => (4, VISIBLE_HEIGHT),
+            BoardSize::Wide => (12, VISIBLE_HEIGHT),
+            BoardSize::Tall => (BOARD_WIDTH, 40),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            BoardSize::Standard => BoardSize::Wide,
+            BoardSize::Wide => BoardSize::Tall,
+            BoardSize::Tall => BoardSize::Narrow,
+            BoardSize::Narrow => BoardSize::Standard,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WindowModeSetting {
+    #[default]
+    Windowed,
+    BorderlessFullscreen,
+    Fullscreen,
+}
+
+impl From<WindowModeSetting> for WindowMode {
+    fn from(mode: WindowModeSetting) -> Self {
+        match mode {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::BorderlessFullscreen => {
+                WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+            }
+            WindowModeSetting::Fullscreen => {
+                WindowMode::Fullscreen(MonitorSelection::Current, VideoModeSelection::Current)
+            }
+        }
+    }
+}
+
+/// Why the settings file couldn't be used, shown on the main menu. The game runs on defaults,
+/// and the file is copied to [`SETTINGS_BACKUP`] before the first changed setting overwrites
+/// it.
+#[derive(Resource, Default)]
+pub struct SettingsLoadError(pub Option<String>);
+
+/// Where `file` lives in the user's config dir, if the platform has one.
+pub fn config_file(file: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR).join(file))
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    config_file(SETTINGS_FILE)
+}
+
+impl Settings {
+    /// Read the settings file, or the defaults if there isn't one yet.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = settings_path() else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => return Err(format!("Could not read {}: {error}", path.display())),
+        };
+        let mut settings: Self = ron::from_str(&text)
+            .map_err(|error| format!("{} is corrupt: {error}", path.display()))?;
+        settings.validate();
+        Ok(settings)
+    }
+
+    /// Write the settings file. With `back_up`, for a file that couldn't be loaded, it is
+    /// copied to [`SETTINGS_BACKUP`] first, and left alone if that copy fails.
+    pub fn save(&self, back_up: bool) -> Result<(), String> {
+        let Some(path) = settings_path() else {
+            return Err("No config directory to save settings in".to_string());
+        };
+        if back_up {
+            back_up_file(&path, SETTINGS_BACKUP)?;
+        }
+        write_ron(&path, self)
+    }
+
+    /// Bring every value back into its allowed range, and give actions missing from the file
+    /// their default bindings.
+    pub fn validate(&mut self) {
+        let defaults = Self::default();
+        let handling = &mut self.handling;
+        handling.das_ms = clamp_or(handling.das_ms, 0.0, 1000.0, defaults.handling.das_ms);
+        handling.arr_ms = clamp_or(handling.arr_ms, 0.0, 500.0, defaults.handling.arr_ms);
+        handling.soft_drop_factor = clamp_or(
+            handling.soft_drop_factor,
+            1.0,
+            100.0,
+            defaults.handling.soft_drop_factor,
+        );
+
+        let audio = &mut self.audio;
+        audio.master = clamp_or(audio.master, 0.0, 1.0, defaults.audio.master);
+        audio.music = clamp_or(audio.music, 0.0, 1.0, defaults.audio.music);
+        audio.effects = clamp_or(audio.effects, 0.0, 1.0, defaults.audio.effects);
+
+        let visuals = &mut self.visuals;
+        visuals.ghost_alpha = clamp_or(visuals.ghost_alpha, 0.0, 1.0, defaults.visuals.ghost_alpha);
+        visuals.next_count = visuals.next_count.clamp(1, LOOKAHEAD);
+
+        self.bindings.repair();
+    }
+}
+
+/// Serialize `value` as pretty RON to `path`, creating its directory if needed.
+pub fn write_ron<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+    }
+    fs::write(path, text).map_err(|error| format!("Could not write {}: {error}", path.display()))
+}
+
+/// Copy `path` to `backup` in the same directory before it gets overwritten. A missing file
+/// needs no backup.
+pub fn back_up_file(path: &Path, backup: &str) -> Result<(), String> {
+    let backup = path.with_file_name(backup);
+    match fs::copy(path, &backup) {
+        Ok(_) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(format!(
+            "Not saving: could not back up {} to {}: {error}",
+            path.display(),
+            backup.display()
+        )),
+    }
+}
+
+fn clamp_or(value: f32, min: f32, max: f32, default: f32) -> f32 {
+    if value.is_finite() {
+        value.clamp(min, max)
+    } else {
+        default
+    }
+}
+
+/// Push changed settings out to the resources the rest of the game reads.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_settings_system(
+    settings: Res<Settings>,
+    mut rules: ResMut<GameRules>,
+    game: Option<ResMut<Game>>,
+    mut input_map: ResMut<InputMap>,
+    mut ghost_config: ResMut<GhostConfig>,
+    mut preview_config: ResMut<PreviewConfig>,
+    mut palette: ResMut<Palette>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let handling = &settings.handling;
+    rules.handling = Handling {
+        das: ms_to_ticks(handling.das_ms),
+        arr: ms_to_ticks(handling.arr_ms),
+    };
+    rules.soft_drop = if handling.sonic_drop {
+        SoftDrop::Sonic
+    } else {
+        SoftDrop::Factor(handling.soft_drop_factor.round() as u32)
+    };
+    (rules.board_width, rules.visible_height) = settings.board_size.dimensions();
+    // Handling is safe to change mid-game; the rest of the rules wait for the next one.
+    if let Some(mut game) = game {
+        game.config.handling = rules.handling.clone();
+        game.config.soft_drop = rules.soft_drop;
+    }
+    input_map.set_if_neq(settings.bindings.clone());
+
+    let visuals = &settings.visuals;
+    ghost_config.enabled = visuals.ghost;
+    ghost_config.alpha = visuals.ghost_alpha;
+    preview_config.next_count = visuals.next_count;
+    *palette = visuals.palette.palette();
+
+    if let Ok(mut window) = windows.single_mut() {
+        let mode = WindowMode::from(settings.window_mode);
+        if window.mode != mode {
+            window.mode = mode;
+        }
+    }
+}
+
+/// Write the settings file after the player changes something.
+pub fn save_settings_system(settings: Res<Settings>, mut load_error: ResMut<SettingsLoadError>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    match settings.save(load_error.0.is_some()) {
+        Ok(()) => load_error.0 = None,
+        Err(error) => warn!("{error}"),
+    }
+}
@@ -6,4 +6,30 @@ pub enum AppState {
     MainMenu,
     InGame,
     GameOver,
+    HighScores,
+    /// The list of saved replays.
+    Replays,
+    /// Watching one replay.
+    ReplayViewer,
+}
+
+/// Whether a game in progress is running or paused, and which pause panel is open.
+#[derive(SubStates, Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[source(AppState = AppState::InGame)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+    Settings,
+    /// The rebinding screen, opened from settings.
+    Controls,
+}
+
+/// Whether the board is on screen and moving: a game running unpaused, or a replay.
+pub fn board_active(app: Res<State<AppState>>, pause: Option<Res<State<PauseState>>>) -> bool {
+    match app.get() {
+        AppState::InGame => pause.is_some_and(|pause| *pause.get() == PauseState::Running),
+        AppState::ReplayViewer => true,
+        _ => false,
+    }
 }
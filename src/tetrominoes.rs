@@ -1,60 +1,74 @@
 use crate::board::*;
-use bevy::prelude::*;
-use rand::Rng;
-#[derive(Component, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TetrominoKind { I, O, T, S, Z, J, L }
 
+impl TetrominoKind {
+    pub const ALL: [TetrominoKind; 7] = [
+        TetrominoKind::I,
+        TetrominoKind::O,
+        TetrominoKind::T,
+        TetrominoKind::S,
+        TetrominoKind::Z,
+        TetrominoKind::J,
+        TetrominoKind::L,
+    ];
+}
+
 pub struct TetrominoShape{
     pub cells: &'static [(i32,i32)]
 }
 
+// SRS rotation states 0, R, 2, L as offsets inside the piece's bounding box (y grows upwards,
+// so the top row of a 3x3 box is y = 2). Each state is the true rotation of the spawn state
+// about the box centre, which is what the kick tables below assume.
+
 const I_SHAPES: [[(i32, i32); 4]; 4] = [
-    [(0, 0), (1, 0), (2, 0), (3, 0)],        // 0°
-    [(1, -1), (1, 0), (1, 1), (1, 2)],       // 90°
-    [(0, 1), (1, 1), (2, 1), (3, 1)],        // 180°
-    [(2, -1), (2, 0), (2, 1), (2, 2)],       // 270°
+    [(0, 2), (1, 2), (2, 2), (3, 2)],        // 0
+    [(2, 3), (2, 2), (2, 1), (2, 0)],        // R
+    [(0, 1), (1, 1), (2, 1), (3, 1)],        // 2
+    [(1, 3), (1, 2), (1, 1), (1, 0)],        // L
 ];
 
 const O_SHAPES: [[(i32, i32); 4]; 4] = [
-    [(0, 0), (1, 0), (0, 1), (1, 1)],        // 0°
-    [(0, 0), (1, 0), (0, 1), (1, 1)],        // 90° (same)
-    [(0, 0), (1, 0), (0, 1), (1, 1)],        // 180°
-    [(0, 0), (1, 0), (0, 1), (1, 1)],        // 270°
+    [(1, 2), (2, 2), (1, 1), (2, 1)],        // 0
+    [(1, 2), (2, 2), (1, 1), (2, 1)],        // R (same)
+    [(1, 2), (2, 2), (1, 1), (2, 1)],        // 2
+    [(1, 2), (2, 2), (1, 1), (2, 1)],        // L
 ];
 
 const T_SHAPES: [[(i32, i32); 4]; 4] = [
-    [(0, 0), (1, 0), (2, 0), (1, 1)],        // 0°   _T_
-    [(1, -1), (1, 0), (1, 1), (2, 0)],       // 90°   ⟂ right
-    [(0, 1), (1, 1), (2, 1), (1, 0)],        // 180°
-    [(1, -1), (1, 0), (1, 1), (0, 0)],       // 270°  ⟂ left
+    [(1, 2), (0, 1), (1, 1), (2, 1)],        // 0   pointing up
+    [(1, 2), (1, 1), (2, 1), (1, 0)],        // R   pointing right
+    [(0, 1), (1, 1), (2, 1), (1, 0)],        // 2   pointing down
+    [(1, 2), (0, 1), (1, 1), (1, 0)],        // L   pointing left
 ];
 
 const S_SHAPES: [[(i32, i32); 4]; 4] = [
-    [(1, 0), (2, 0), (0, 1), (1, 1)],        // 0°   horizontal S
-    [(1, -1), (1, 0), (2, 0), (2, 1)],       // 90°
-    [(1, 0), (2, 0), (0, 1), (1, 1)],        // 180°
-    [(1, -1), (1, 0), (2, 0), (2, 1)],       // 270°
+    [(1, 2), (2, 2), (0, 1), (1, 1)],        // 0
+    [(1, 2), (1, 1), (2, 1), (2, 0)],        // R
+    [(1, 1), (2, 1), (0, 0), (1, 0)],        // 2
+    [(0, 2), (0, 1), (1, 1), (1, 0)],        // L
 ];
 
 const Z_SHAPES: [[(i32, i32); 4]; 4] = [
-    [(0, 0), (1, 0), (1, 1), (2, 1)],        // 0°   horizontal Z
-    [(2, -1), (1, 0), (2, 0), (1, 1)],       // 90°
-    [(0, 0), (1, 0), (1, 1), (2, 1)],        // 180°
-    [(2, -1), (1, 0), (2, 0), (1, 1)],       // 270°
+    [(0, 2), (1, 2), (1, 1), (2, 1)],        // 0
+    [(2, 2), (1, 1), (2, 1), (1, 0)],        // R
+    [(0, 1), (1, 1), (1, 0), (2, 0)],        // 2
+    [(1, 2), (0, 1), (1, 1), (0, 0)],        // L
 ];
 
 const J_SHAPES: [[(i32, i32); 4]; 4] = [
-    [(0, 0), (0, 1), (1, 1), (2, 1)],        // 0°   ┘ flipped
-    [(1, -1), (2, -1), (1, 0), (1, 1)],      // 90°
-    [(0, 0), (1, 0), (2, 0), (2, 1)],        // 180°
-    [(1, -1), (1, 0), (0, 1), (1, 1)],       // 270°
+    [(0, 2), (0, 1), (1, 1), (2, 1)],        // 0
+    [(1, 2), (2, 2), (1, 1), (1, 0)],        // R
+    [(0, 1), (1, 1), (2, 1), (2, 0)],        // 2
+    [(1, 2), (1, 1), (0, 0), (1, 0)],        // L
 ];
 
 const L_SHAPES: [[(i32, i32); 4]; 4] = [
-    [(2, 0), (0, 1), (1, 1), (2, 1)],        // 0°   └
-    [(1, -1), (1, 0), (1, 1), (2, 1)],       // 90°
-    [(0, 0), (1, 0), (2, 0), (0, 1)],        // 180°
-    [(0, -1), (1, -1), (1, 0), (1, 1)],      // 270°
+    [(2, 2), (0, 1), (1, 1), (2, 1)],        // 0
+    [(1, 2), (1, 1), (1, 0), (2, 0)],        // R
+    [(0, 1), (1, 1), (2, 1), (0, 0)],        // 2
+    [(0, 2), (1, 2), (1, 1), (1, 0)],        // L
 ];
 
 pub fn shape_of(kind: TetrominoKind, rotation: u8) -> TetrominoShape {
@@ -70,62 +84,206 @@ pub fn shape_of(kind: TetrominoKind, rotation: u8) -> TetrominoShape {
     }
 }
 
+/// Which way a rotation input turns the piece.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    Cw,
+    Ccw,
+    Half,
+}
+
+impl Rotation {
+    /// The rotation state reached by turning from `from`.
+    pub fn apply(self, from: u8) -> u8 {
+        let steps = match self {
+            Rotation::Cw => 1,
+            Rotation::Ccw => 3,
+            Rotation::Half => 2,
+        };
+        (from + steps) % 4
+    }
+}
+
+// Wall kick offsets (dx, dy), y up, tried in order. Indexed by the starting state; the first
+// table of each pair is for clockwise turns (0->R, R->2, 2->L, L->0), the second for
+// counter-clockwise turns (0->L, R->0, 2->R, L->2).
+
+const JLSTZ_KICKS_CW: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // 0 -> R
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],     // R -> 2
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],    // 2 -> L
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],  // L -> 0
+];
 
+const JLSTZ_KICKS_CCW: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],    // 0 -> L
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],     // R -> 0
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // 2 -> R
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],  // L -> 2
+];
 
-#[derive(Resource)]
+const I_KICKS_CW: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],   // 0 -> R
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],   // R -> 2
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],   // 2 -> L
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],   // L -> 0
+];
+
+const I_KICKS_CCW: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],   // 0 -> L
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],   // R -> 0
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],   // 2 -> R
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],   // L -> 2
+];
+
+// SRS itself has no 180° turns; these are the widely used SRS+ kicks (0->2, R->L, 2->0, L->R).
+const HALF_KICKS: [[(i32, i32); 6]; 4] = [
+    [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+    [(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+    [(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+    [(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+];
+
+/// Offsets to try, in order, when turning a `kind` piece out of rotation state `from`.
+pub fn kicks_for(kind: TetrominoKind, from: u8, rotation: Rotation) -> &'static [(i32, i32)] {
+    let r = (from % 4) as usize;
+    match (kind, rotation) {
+        (TetrominoKind::O, _) => &[(0, 0)],
+        (_, Rotation::Half) => &HALF_KICKS[r],
+        (TetrominoKind::I, Rotation::Cw) => &I_KICKS_CW[r],
+        (TetrominoKind::I, Rotation::Ccw) => &I_KICKS_CCW[r],
+        (_, Rotation::Cw) => &JLSTZ_KICKS_CW[r],
+        (_, Rotation::Ccw) => &JLSTZ_KICKS_CCW[r],
+    }
+}
+
+
+#[derive(Clone, Debug)]
 pub struct ActivePiece{
     pub kind: TetrominoKind,
     pub rotation: u8,
     pub x: i32,
     pub y: i32,
-    pub color: Color,
 }
 
 impl ActivePiece {
-    pub fn spawn_new() -> Self {
-        let mut rng = rand::rng();
-
-        let kinds = [
-            TetrominoKind::I,
-            TetrominoKind::O,
-            TetrominoKind::T,
-            TetrominoKind::S,
-            TetrominoKind::Z,
-            TetrominoKind::J,
-            TetrominoKind::L,
-        ];
-        let kind = kinds[rng.random_range(0..kinds.len())].clone();
-
-        // random color
-        let color = Color::srgb(
-            rng.random_range(0.2..1.0),
-            rng.random_range(0.2..1.0),
-            rng.random_range(0.2..1.0),
-        );
+    pub fn spawn_new(kind: TetrominoKind, board: &Board) -> Self {
+        // centred, so where a piece appears depends only on its kind and the board width
+        let x = (board.width() as i32 - 3) / 2;
 
-        // random X so the 4‑wide bounding box stays on the board
-        let x = rng.random_range(0..=(BOARD_WIDTH as i32 - 4));
+        // Bounding box top two rows above the visible field (row 22 of a standard board,
+        // counting from 1), so pieces spawn in the first two rows of the buffer.
+        let box_top = if kind == TetrominoKind::I { 3 } else { 2 };
 
         Self {
             kind,
             rotation: 0,
             x,
-            y: (BOARD_HEIGHT - 2) as i32,
-            color,
+            y: board.visible_height() as i32 + 1 - box_top,
+        }
+    }
+
+    /// Absolute board positions of the piece's four blocks.
+    pub fn cells(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        shape_of(self.kind, self.rotation)
+            .cells
+            .iter()
+            .map(|(dx, dy)| (self.x + dx, self.y + dy))
+    }
+
+    /// A copy of this piece shifted by `(dx, dy)`.
+    pub fn moved(&self, dx: i32, dy: i32) -> Self {
+        Self {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..self.clone()
+        }
+    }
+
+    /// A copy of this piece in the given rotation state.
+    pub fn rotated(&self, rotation: u8) -> Self {
+        Self {
+            rotation: rotation % 4,
+            ..self.clone()
         }
     }
 }
 
-pub fn place_active_on_board(active: &ActivePiece, board: &mut Board) {
-    let shape = shape_of(active.kind.clone(), active.rotation);
-    for (dx, dy) in shape.cells {
-        board.set(active.x + dx, active.y + dy, Cell::Filled(active.color));
+/// Whether every block of `active` is on the board (the hidden buffer rows included) and not
+/// overlapping the locked stack.
+pub fn can_place(active: &ActivePiece, board: &Board) -> bool {
+    active
+        .cells()
+        .all(|(x, y)| board.get(x, y) == Some(Cell::Empty))
+}
+
+/// Write the blocks of `active` into the board's locked cells.
+pub fn lock_piece(active: &ActivePiece, board: &mut Board) {
+    for (x, y) in active.cells() {
+        board.set(x, y, Cell::Filled(active.kind));
     }
 }
 
-pub fn clear_active_from_board(active: &ActivePiece, board: &mut Board) {
-    let shape = shape_of(active.kind.clone(), active.rotation);
-    for (dx, dy) in shape.cells {
-        board.set(active.x + dx, active.y + dy, Cell::Empty);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first kick offset that lets `piece` turn by `rotation` on `board`.
+    fn first_fitting_kick(
+        piece: &ActivePiece,
+        rotation: Rotation,
+        board: &Board,
+    ) -> Option<(i32, i32)> {
+        let rotated = piece.rotated(rotation.apply(piece.rotation));
+        kicks_for(piece.kind, piece.rotation, rotation)
+            .iter()
+            .copied()
+            .find(|&(dx, dy)| can_place(&rotated.moved(dx, dy), board))
+    }
+
+    #[test]
+    fn rotation_states_cycle() {
+        assert_eq!(Rotation::Cw.apply(3), 0);
+        assert_eq!(Rotation::Ccw.apply(0), 3);
+        assert_eq!(Rotation::Half.apply(1), 3);
+    }
+
+    #[test]
+    fn srs_kick_tables_match_the_guideline() {
+        assert_eq!(
+            kicks_for(TetrominoKind::T, 0, Rotation::Cw),
+            &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]
+        );
+        assert_eq!(
+            kicks_for(TetrominoKind::I, 0, Rotation::Cw),
+            &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]
+        );
+        assert_eq!(kicks_for(TetrominoKind::O, 2, Rotation::Ccw), &[(0, 0)]);
+    }
+
+    #[test]
+    fn t_turning_right_on_the_floor_against_the_left_wall_kicks_up_and_left() {
+        let board = Board::default();
+        let t = ActivePiece {
+            kind: TetrominoKind::T,
+            rotation: 0,
+            x: 0,
+            y: -1,
+        };
+        assert!(can_place(&t, &board));
+        assert_eq!(first_fitting_kick(&t, Rotation::Cw, &board), Some((-1, 1)));
+    }
+
+    #[test]
+    fn t_turning_back_from_the_left_wall_kicks_right() {
+        let board = Board::default();
+        let t = ActivePiece {
+            kind: TetrominoKind::T,
+            rotation: 1,
+            x: -1,
+            y: 5,
+        };
+        assert!(can_place(&t, &board));
+        assert_eq!(first_fitting_kick(&t, Rotation::Ccw, &board), Some((1, 0)));
     }
 }